@@ -0,0 +1,134 @@
+//! Stable topic slugs replacing player names in topics.
+
+use serde_json::{json, Value};
+use std::collections::BTreeMap;
+
+use crate::mqtt::PLUGIN_PLAYER_NAME;
+
+/// Topic slugs by player name.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Aliases(BTreeMap<String, String>);
+
+impl Aliases {
+    fn new(slugs: BTreeMap<String, String>) -> Result<Self, String> {
+        let mut players = BTreeMap::new();
+        for (player_name, slug) in &slugs {
+            if player_name.is_empty() || slug.is_empty() {
+                return Err("Player names and their aliases can't be empty.".into());
+            }
+            if player_name == PLUGIN_PLAYER_NAME || slug == PLUGIN_PLAYER_NAME {
+                return Err(format!(
+                    "'{PLUGIN_PLAYER_NAME}' is reserved for the plugin."
+                ));
+            }
+            // Commands for the other player would be routed to this one.
+            if slug != player_name && slugs.contains_key(slug) {
+                return Err(format!(
+                    "The alias '{slug}' of '{player_name}' is the name of another player."
+                ));
+            }
+            if let Some(other) = players.insert(slug, player_name) {
+                return Err(format!(
+                    "The alias '{slug}' is used for '{other}' and '{player_name}'."
+                ));
+            }
+        }
+        Ok(Aliases(slugs))
+    }
+
+    /// Read one alias per line, given as `<player name> = <slug>`.
+    ///
+    /// The error describes the problem and is meant for the configuration dialog.
+    pub fn from_lines(lines: &str) -> Result<Self, String> {
+        let mut slugs = BTreeMap::new();
+        for line in lines.lines().map(str::trim).filter(|line| !line.is_empty()) {
+            let (player_name, slug) = line
+                .rsplit_once('=')
+                .ok_or_else(|| format!("The line '{line}' lacks '=' between name and alias."))?;
+            let player_name = player_name.trim();
+            if slugs
+                .insert(player_name.into(), slug.trim().into())
+                .is_some()
+            {
+                return Err(format!("There are multiple aliases for '{player_name}'."));
+            }
+        }
+        Aliases::new(slugs)
+    }
+
+    pub fn to_lines(&self) -> String {
+        self.0
+            .iter()
+            .map(|(player_name, slug)| format!("{player_name} = {slug}\n"))
+            .collect()
+    }
+
+    /// Read the aliases from a JSON object as written by [`Aliases::to_value`].
+    pub fn from_value(value: Value) -> Result<Self, String> {
+        let slugs = serde_json::from_value(value).map_err(|e| format!("invalid aliases ({e})"))?;
+        Aliases::new(slugs)
+    }
+
+    pub fn to_value(&self) -> Value {
+        json!(self.0)
+    }
+
+    /// Name of the player in topics.
+    pub fn slug<'a>(&'a self, player_name: &'a str) -> &'a str {
+        self.0.get(player_name).map_or(player_name, String::as_str)
+    }
+
+    /// Name of the player named in a topic, which is either its alias or the player name itself.
+    pub fn player_name<'a>(&'a self, slug: &'a str) -> &'a str {
+        self.0
+            .iter()
+            .find(|(_, s)| *s == slug)
+            .map_or(slug, |(player_name, _)| player_name.as_str())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mapping() {
+        let aliases =
+            Aliases::from_lines("Main Stage – Music = main\n\n  FX = a=b = fx  \n").unwrap();
+
+        assert_eq!(aliases.slug("Main Stage – Music"), "main");
+        assert_eq!(aliases.slug("FX = a=b"), "fx");
+        assert_eq!(aliases.slug("Other"), "Other");
+        assert_eq!(aliases.player_name("main"), "Main Stage – Music");
+        assert_eq!(aliases.player_name("Other"), "Other");
+        assert_eq!(
+            aliases.to_lines(),
+            "FX = a=b = fx\nMain Stage – Music = main\n"
+        );
+    }
+
+    #[test]
+    fn invalid() {
+        assert!(Aliases::from_lines("Main").is_err());
+        assert!(Aliases::from_lines("Main =").is_err());
+        assert!(Aliases::from_lines("= main").is_err());
+        assert!(Aliases::from_lines("Main = a\nMain = b").is_err());
+        assert!(Aliases::from_lines("Main = a\nOther = a").is_err());
+        assert!(Aliases::from_value(json!(["main"])).is_err());
+
+        let error = Aliases::from_lines("Main\nStage = main").unwrap_err();
+        assert!(error.contains("Main"), "{error}");
+    }
+
+    #[test]
+    fn collisions() {
+        let error = Aliases::from_lines("A = B\nB = b").unwrap_err();
+        assert!(error.contains("another player"), "{error}");
+        assert!(Aliases::from_lines("A = a\nB = b").is_ok());
+        assert!(Aliases::from_lines("main = main").is_ok());
+
+        assert!(Aliases::from_lines("Main = _plugin").is_err());
+        assert!(Aliases::from_lines("_plugin = main").is_err());
+        assert!(Aliases::from_value(json!({ "Main": "_plugin" })).is_err());
+    }
+}
@@ -0,0 +1,130 @@
+//! Delays between attempts to reconnect to the broker.
+//!
+//! The delay doubles with every failed attempt up to a maximum. Jitter shortens each delay by a
+//! random share, so that several machines losing the broker at the same time don't reconnect in
+//! lockstep.
+
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::time::Duration;
+
+/// How reconnecting is paced.
+pub struct Policy {
+    pub initial_delay: Duration,
+    pub max_delay: Duration,
+    /// Percentage by which a delay is shortened at most.
+    pub jitter: u32,
+    /// Attempts before giving up, unlimited if 0.
+    pub max_attempts: u32,
+}
+
+pub struct Backoff {
+    policy: Policy,
+    /// Failed connection attempts since the connection was last established.
+    attempts: u32,
+}
+
+/// Random number between 0 and 1.
+///
+/// The hasher of every `RandomState` is seeded differently, which is random enough for jitter.
+fn random() -> f64 {
+    RandomState::new().build_hasher().finish() as f64 / u64::MAX as f64
+}
+
+impl Backoff {
+    pub fn new(policy: Policy) -> Self {
+        Backoff {
+            policy,
+            attempts: 0,
+        }
+    }
+
+    /// Number of the upcoming attempt, 0 while connected.
+    pub fn attempts(&self) -> u32 {
+        self.attempts
+    }
+
+    /// Record that the connection failed, returning the delay before the next attempt or `None`
+    /// if the attempts are used up.
+    pub fn failed(&mut self) -> Option<Duration> {
+        self.failed_with(random())
+    }
+
+    fn failed_with(&mut self, random: f64) -> Option<Duration> {
+        self.attempts = self.attempts.saturating_add(1);
+        if self.policy.max_attempts > 0 && self.attempts > self.policy.max_attempts {
+            return None;
+        }
+
+        let factor = 2u32.saturating_pow(self.attempts - 1);
+        let delay = self
+            .policy
+            .initial_delay
+            .saturating_mul(factor)
+            .min(self.policy.max_delay);
+        let jitter = f64::from(self.policy.jitter.min(100)) / 100.0;
+        Some(delay.mul_f64(1.0 - jitter * random))
+    }
+
+    /// Record that the connection was established, returning the attempts it took.
+    pub fn reset(&mut self) -> u32 {
+        std::mem::take(&mut self.attempts)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn policy(jitter: u32, max_attempts: u32) -> Policy {
+        Policy {
+            initial_delay: Duration::from_secs(1),
+            max_delay: Duration::from_secs(10),
+            jitter,
+            max_attempts,
+        }
+    }
+
+    #[test]
+    fn exponential() {
+        let mut backoff = Backoff::new(policy(0, 0));
+        let delays: Vec<_> = (0..6)
+            .map(|_| backoff.failed().unwrap().as_secs())
+            .collect();
+        assert_eq!(delays, [1, 2, 4, 8, 10, 10]);
+        assert_eq!(backoff.attempts(), 6);
+
+        // Doubling doesn't overflow however long the broker is away.
+        for _ in 0..100 {
+            assert_eq!(backoff.failed(), Some(Duration::from_secs(10)));
+        }
+
+        assert_eq!(backoff.reset(), 106);
+        assert_eq!(backoff.failed(), Some(Duration::from_secs(1)));
+    }
+
+    #[test]
+    fn jitter() {
+        let mut backoff = Backoff::new(policy(50, 0));
+        assert_eq!(backoff.failed_with(0.0), Some(Duration::from_secs(1)));
+        assert_eq!(backoff.failed_with(1.0), Some(Duration::from_secs(1)));
+        assert_eq!(backoff.failed_with(0.5), Some(Duration::from_secs(3)));
+        assert_eq!(backoff.failed_with(0.0), Some(Duration::from_secs(8)));
+
+        for _ in 0..100 {
+            let delay = backoff.failed().unwrap();
+            assert!(delay >= Duration::from_secs(5) && delay <= Duration::from_secs(10));
+        }
+    }
+
+    #[test]
+    fn give_up() {
+        let mut backoff = Backoff::new(policy(0, 2));
+        assert!(backoff.failed().is_some());
+        assert!(backoff.failed().is_some());
+        assert_eq!(backoff.failed(), None);
+
+        backoff.reset();
+        assert!(backoff.failed().is_some());
+    }
+}
@@ -0,0 +1,262 @@
+//! Configuration text stored by M*Player in its project files.
+//!
+//! The configuration is a JSON object with a `version` key. Keys which aren't known are kept, so
+//! saving a configuration written by a newer version of the plugin doesn't lose its settings.
+//! Older versions of the plugin stored newline-separated fields instead, which are still read.
+
+use log::warn;
+use serde_json::{json, Map, Value};
+use std::fmt::Display;
+use std::str::FromStr;
+
+use crate::{aliases, Configuration};
+
+/// Version of the configuration format written by this plugin.
+const VERSION: u64 = 1;
+
+/// Parse the newline-separated fields written before the configuration was versioned.
+///
+/// Only the released format with server, port, client name and topic prefix is migrated.
+fn parse_legacy(text: &str) -> Result<Configuration, String> {
+    let parts: Vec<&str> = text.split('\n').collect();
+    let [server, port, client_name, topic_prefix] = parts[..] else {
+        return Err(format!(
+            "Malformed configuration: expected 4 lines but got {}.",
+            parts.len()
+        ));
+    };
+
+    Ok(Configuration {
+        server: server.into(),
+        port: port
+            .parse()
+            .map_err(|e| format!("Malformed port in configuration: {e}"))?,
+        client_name: client_name.into(),
+        topic_prefix: topic_prefix.into(),
+        ..Default::default()
+    })
+}
+
+/// Fields of a versioned configuration, removed from the object when they're read.
+struct Fields(Map<String, Value>);
+
+impl Fields {
+    /// Take the value of `key` and convert it, using `default` if the key is missing.
+    fn take<T>(
+        &mut self,
+        key: &str,
+        default: T,
+        convert: impl FnOnce(Value) -> Option<T>,
+    ) -> Result<T, String> {
+        match self.0.remove(key) {
+            None => Ok(default),
+            Some(value) => {
+                let text = value.to_string();
+                convert(value).ok_or_else(|| format!("Malformed {key} in configuration: {text}"))
+            }
+        }
+    }
+
+    fn string(&mut self, key: &str, default: String) -> Result<String, String> {
+        self.take(key, default, |value| match value {
+            Value::String(s) => Some(s),
+            _ => None,
+        })
+    }
+
+    fn bool(&mut self, key: &str, default: bool) -> Result<bool, String> {
+        self.take(key, default, |value| value.as_bool())
+    }
+
+    fn number<T: TryFrom<u64>>(&mut self, key: &str, default: T) -> Result<T, String> {
+        self.take(key, default, |value| {
+            value.as_u64().and_then(|n| T::try_from(n).ok())
+        })
+    }
+
+    /// A string parsed by `FromStr`.
+    fn parsed<T>(&mut self, key: &str, default: T) -> Result<T, String>
+    where
+        T: FromStr,
+        T::Err: Display,
+    {
+        match self.0.remove(key) {
+            None => Ok(default),
+            Some(Value::String(s)) => s
+                .parse()
+                .map_err(|e| format!("Malformed {key} in configuration: {e}")),
+            Some(value) => Err(format!("Malformed {key} in configuration: {value}")),
+        }
+    }
+}
+
+fn parse_versioned(text: &str) -> Result<Configuration, String> {
+    let object = match serde_json::from_str(text) {
+        Ok(Value::Object(object)) => object,
+        Ok(_) => return Err("Configuration isn't a JSON object.".into()),
+        Err(e) => return Err(format!("Malformed configuration: {e}")),
+    };
+    let mut fields = Fields(object);
+
+    let version = fields.number("version", 0u64)?;
+    if version == 0 {
+        return Err("Configuration lacks its version.".into());
+    }
+    if version > VERSION {
+        warn!("Configuration was saved by a newer version ({version}), settings might be missing.");
+    }
+
+    let default = Configuration::default();
+
+    Ok(Configuration {
+        server: fields.string("server", default.server)?,
+        port: fields.number("port", default.port)?,
+        client_name: fields.string("client_name", default.client_name)?,
+        topic_prefix: fields.string("topic_prefix", default.topic_prefix)?,
+        payload_format: fields.parsed("payload_format", default.payload_format)?,
+        position_interval: fields.number("position_interval", default.position_interval)?,
+        position_min_delta: fields.number("position_min_delta", default.position_min_delta)?,
+        tls: fields.bool("tls", default.tls)?,
+        tls_ca_file: fields.string("tls_ca_file", default.tls_ca_file)?,
+        tls_client_certificate_file: fields.string(
+            "tls_client_certificate_file",
+            default.tls_client_certificate_file,
+        )?,
+        tls_client_key_file: fields.string("tls_client_key_file", default.tls_client_key_file)?,
+        tls_skip_hostname_verification: fields.bool(
+            "tls_skip_hostname_verification",
+            default.tls_skip_hostname_verification,
+        )?,
+        username: fields.string("username", default.username)?,
+        password: fields.string("password", default.password)?,
+        protocol_version: fields.parsed("protocol_version", default.protocol_version)?,
+        position_expiry: fields.number("position_expiry", default.position_expiry)?,
+        case_insensitive_player_names: fields.bool(
+            "case_insensitive_player_names",
+            default.case_insensitive_player_names,
+        )?,
+        monitor_topic_template: fields
+            .parsed("monitor_topic_template", default.monitor_topic_template)?,
+        control_topic_template: fields
+            .parsed("control_topic_template", default.control_topic_template)?,
+        aliases: match fields.0.remove("aliases") {
+            None => default.aliases,
+            Some(value) => aliases::Aliases::from_value(value)
+                .map_err(|e| format!("Malformed aliases in configuration: {e}"))?,
+        },
+        reconnect_delay: fields.number("reconnect_delay", default.reconnect_delay)?,
+        reconnect_max_delay: fields.number("reconnect_max_delay", default.reconnect_max_delay)?,
+        reconnect_jitter: fields.number("reconnect_jitter", default.reconnect_jitter)?,
+        reconnect_attempts: fields.number("reconnect_attempts", default.reconnect_attempts)?,
+        extra: fields.0,
+    })
+}
+
+/// Parse the configuration text, migrating configurations of older versions.
+///
+/// The error describes why the configuration was rejected.
+pub fn parse(text: &str) -> Result<Configuration, String> {
+    if text.trim_start().starts_with('{') {
+        parse_versioned(text)
+    } else {
+        parse_legacy(text)
+    }
+}
+
+/// Write the configuration in the current version of the format.
+pub fn to_text(config: &Configuration) -> String {
+    let mut object = config.extra.clone();
+    let known = json!({
+        "version": VERSION,
+        "server": config.server,
+        "port": config.port,
+        "client_name": config.client_name,
+        "topic_prefix": config.topic_prefix,
+        "payload_format": config.payload_format.as_str(),
+        "position_interval": config.position_interval,
+        "position_min_delta": config.position_min_delta,
+        "tls": config.tls,
+        "tls_ca_file": config.tls_ca_file,
+        "tls_client_certificate_file": config.tls_client_certificate_file,
+        "tls_client_key_file": config.tls_client_key_file,
+        "tls_skip_hostname_verification": config.tls_skip_hostname_verification,
+        "username": config.username,
+        "password": config.password,
+        "protocol_version": config.protocol_version.as_str(),
+        "position_expiry": config.position_expiry,
+        "case_insensitive_player_names": config.case_insensitive_player_names,
+        "monitor_topic_template": config.monitor_topic_template.to_string(),
+        "control_topic_template": config.control_topic_template.to_string(),
+        "aliases": config.aliases.to_value(),
+        "reconnect_delay": config.reconnect_delay,
+        "reconnect_max_delay": config.reconnect_max_delay,
+        "reconnect_jitter": config.reconnect_jitter,
+        "reconnect_attempts": config.reconnect_attempts,
+    });
+    if let Value::Object(known) = known {
+        object.extend(known);
+    }
+    Value::Object(object).to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{PayloadFormat, ProtocolVersion};
+
+    #[test]
+    fn legacy() {
+        let config = parse("127.0.0.1\n1\nclient\ntopic").unwrap();
+        assert_eq!(config.server, "127.0.0.1");
+        assert_eq!(config.port, 1);
+        assert_eq!(config.client_name, "client");
+        assert_eq!(config.topic_prefix, "topic");
+        assert_eq!(config.payload_format, PayloadFormat::Raw);
+        assert_eq!(config.protocol_version, ProtocolVersion::V311);
+
+        assert!(parse("127.0.0.1\n1\nclient").is_err());
+        assert!(parse("127.0.0.1\nport\nclient\ntopic").is_err());
+        // Only the released format is migrated.
+        assert!(parse("127.0.0.1\n1\nclient\ntopic\njson").is_err());
+    }
+
+    #[test]
+    fn migration() {
+        let config = parse("127.0.0.1\n1\nclient\ntopic").unwrap();
+        let text = to_text(&config);
+        let value: Value = serde_json::from_str(&text).unwrap();
+        assert_eq!(value["version"], VERSION);
+        assert_eq!(value["port"], 1);
+        assert_eq!(value["payload_format"], "raw");
+        assert_eq!(value["aliases"], json!({}));
+
+        let migrated = parse(&text).unwrap();
+        assert_eq!(to_text(&migrated), text);
+    }
+
+    #[test]
+    fn unknown_keys() {
+        let config =
+            parse(r#"{"version":2,"server":"broker","future":{"enabled":true},"port":1884}"#)
+                .unwrap();
+        assert_eq!(config.server, "broker");
+        assert_eq!(config.port, 1884);
+        assert_eq!(config.client_name, "MStarPlayer");
+
+        let value: Value = serde_json::from_str(&to_text(&config)).unwrap();
+        assert_eq!(value["future"], json!({ "enabled": true }));
+        assert_eq!(value["version"], VERSION);
+    }
+
+    #[test]
+    fn invalid() {
+        assert!(parse(r#"{"server":"broker"}"#).is_err());
+        assert!(parse(r#"{"version":1,"port":65536}"#).is_err());
+        assert!(parse(r#"{"version":1,"port":"1883"}"#).is_err());
+        assert!(parse(r#"{"version":1,"tls":1}"#).is_err());
+        assert!(parse(r#"{"version":1,"payload_format":"xml"}"#).is_err());
+        assert!(parse(r#"{"version":1,"aliases":{"Main":""}}"#).is_err());
+        assert!(parse(r#"{"version":1,"#).is_err());
+        assert!(parse("[1]").is_err());
+    }
+}
@@ -1,20 +1,133 @@
 #![allow(non_snake_case)]
 
+mod aliases;
+mod backoff;
+mod config;
 mod mqtt;
+mod players;
 pub mod plugin_interface_v2;
+mod state;
+mod status;
+mod sync;
+mod text;
+mod throttle;
+mod tls;
+mod topic;
+mod validation;
 
 use core::ffi::{c_char, c_double, c_int};
-use log::{debug, error, info, warn};
+use log::{debug, error, warn};
 use plugin_interface_v2::*;
-use rumqttc::QoS;
+use serde_json::json;
+use std::borrow::Cow;
 use std::ffi::{CStr, CString};
+use std::panic::{self, AssertUnwindSafe};
+use std::str::FromStr;
 use std::sync::RwLock;
+use std::thread;
+use std::time::Duration;
+
+/// Format of the payload of published messages.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+enum PayloadFormat {
+    /// Empty payloads or plain values.
+    #[default]
+    Raw,
+    /// JSON objects carrying event name, player name, timestamp and event specific fields.
+    Json,
+}
+
+impl PayloadFormat {
+    fn as_str(self) -> &'static str {
+        match self {
+            PayloadFormat::Raw => "raw",
+            PayloadFormat::Json => "json",
+        }
+    }
+}
+
+impl FromStr for PayloadFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "raw" => Ok(PayloadFormat::Raw),
+            "json" => Ok(PayloadFormat::Json),
+            _ => Err(format!("unknown format '{s}'")),
+        }
+    }
+}
+
+/// MQTT protocol version used to talk to the broker.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+enum ProtocolVersion {
+    #[default]
+    V311,
+    /// Adds user properties, response topics and message expiry.
+    V5,
+}
+
+impl ProtocolVersion {
+    fn as_str(self) -> &'static str {
+        match self {
+            ProtocolVersion::V311 => "3.1.1",
+            ProtocolVersion::V5 => "5",
+        }
+    }
+}
+
+impl FromStr for ProtocolVersion {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "3.1.1" => Ok(ProtocolVersion::V311),
+            "5" => Ok(ProtocolVersion::V5),
+            _ => Err(format!("unknown protocol version '{s}'")),
+        }
+    }
+}
 
 struct Configuration {
     server: String,
     port: u16,
     client_name: String,
     topic_prefix: String,
+    payload_format: PayloadFormat,
+    /// Minimum time in milliseconds between published positions of a player.
+    position_interval: u32,
+    /// Minimum change in milliseconds before a position of a player is published again.
+    position_min_delta: u32,
+    tls: bool,
+    /// PEM file with the CA certificates, the platform's certificates are used if empty.
+    tls_ca_file: String,
+    /// PEM files for mutual TLS, no client certificate is sent if empty.
+    tls_client_certificate_file: String,
+    tls_client_key_file: String,
+    tls_skip_hostname_verification: bool,
+    /// User name for the broker, no credentials are sent if empty.
+    username: String,
+    password: String,
+    protocol_version: ProtocolVersion,
+    /// Seconds after which the broker discards undelivered positions, only with MQTT 5.
+    /// Positions don't expire if 0.
+    position_expiry: u32,
+    /// Whether control commands may spell player names with different case.
+    case_insensitive_player_names: bool,
+    monitor_topic_template: topic::MonitorTemplate,
+    control_topic_template: topic::ControlTemplate,
+    /// Names used in topics instead of the player names.
+    aliases: aliases::Aliases,
+    /// Milliseconds before the first attempt to reconnect, doubled with every failed attempt.
+    reconnect_delay: u32,
+    /// Maximum milliseconds between two attempts to reconnect.
+    reconnect_max_delay: u32,
+    /// Percentage by which a delay before reconnecting is randomly shortened at most.
+    reconnect_jitter: u32,
+    /// Attempts to reconnect before giving up, unlimited if 0.
+    reconnect_attempts: u32,
+    /// Settings of newer versions of the plugin, kept so that saving doesn't lose them.
+    extra: serde_json::Map<String, serde_json::Value>,
 }
 
 impl Default for Configuration {
@@ -24,6 +137,27 @@ impl Default for Configuration {
             port: 1883,
             client_name: "MStarPlayer".into(),
             topic_prefix: "MStarPlayer".into(),
+            payload_format: PayloadFormat::default(),
+            position_interval: 0,
+            position_min_delta: 0,
+            tls: false,
+            tls_ca_file: String::new(),
+            tls_client_certificate_file: String::new(),
+            tls_client_key_file: String::new(),
+            tls_skip_hostname_verification: false,
+            username: String::new(),
+            password: String::new(),
+            protocol_version: ProtocolVersion::default(),
+            position_expiry: 0,
+            case_insensitive_player_names: false,
+            monitor_topic_template: topic::MonitorTemplate::default(),
+            control_topic_template: topic::ControlTemplate::default(),
+            aliases: aliases::Aliases::default(),
+            reconnect_delay: 1000,
+            reconnect_max_delay: 60000,
+            reconnect_jitter: 20,
+            reconnect_attempts: 0,
+            extra: serde_json::Map::new(),
         }
     }
 }
@@ -32,6 +166,24 @@ static CONFIG: RwLock<Option<Configuration>> = RwLock::new(None);
 
 static INIT: RwLock<Option<Init>> = RwLock::new(None);
 
+/// Run the body of a function called by M*Player, catching any panic.
+///
+/// Unwinding into M*Player would abort it, so a panic is logged and `None` returned instead.
+fn catch_panic<T>(function: &str, f: impl FnOnce() -> T) -> Option<T> {
+    match panic::catch_unwind(AssertUnwindSafe(f)) {
+        Ok(result) => Some(result),
+        Err(payload) => {
+            let message = payload
+                .downcast_ref::<&str>()
+                .copied()
+                .or_else(|| payload.downcast_ref::<String>().map(String::as_str))
+                .unwrap_or("unknown cause");
+            error!("Panic in {function}: {message}");
+            None
+        }
+    }
+}
+
 #[no_mangle]
 pub extern "C" fn mstarPluginVersion() -> c_int {
     2
@@ -39,132 +191,324 @@ pub extern "C" fn mstarPluginVersion() -> c_int {
 
 #[no_mangle]
 pub extern "C" fn mstarInit(init: &Init) {
-    env_logger::init();
-    debug!("mstarInit");
+    catch_panic("mstarInit", || {
+        // M*Player might initialize the plugin again after it was unloaded.
+        let _ = env_logger::try_init();
+        debug!("mstarInit");
 
-    *INIT.write().unwrap() = Some(init.clone());
+        *sync::write(&INIT) = Some(init.clone());
+    });
 }
 
-fn publish(player_name: *const c_char, event: &str, payload: Vec<u8>) {
-    let config = CONFIG.read().unwrap();
-    let config = config.as_ref();
-    let config = match config {
-        Some(config) => config,
+/// Convert a C string handed over by M*Player, logging an error if it's a null pointer.
+///
+/// Strings which aren't valid UTF-8 are decoded as Windows-1252, see [`text`].
+fn str_from_ptr<'a>(s: *const c_char) -> Option<Cow<'a, str>> {
+    match unsafe { text::from_ptr(s) } {
+        Some((s, _)) => Some(s),
         None => {
-            info!("Not publishing message since plugin wasn't configured yet.");
-            return;
+            error!("Received a null pointer instead of a string");
+            None
         }
-    };
-    let prefix = &config.topic_prefix;
+    }
+}
 
-    let player_name = unsafe { CStr::from_ptr(player_name) };
-    let player_name = match player_name.to_str() {
-        Ok(s) => s,
-        Err(e) => {
-            error!("{}", e);
-            return;
-        }
+fn publish<'a>(
+    player_name: *const c_char,
+    event: impl Into<topic::Event<'a>>,
+    payload: Vec<u8>,
+    fields: serde_json::Value,
+    retain: bool,
+) {
+    let player_name = match str_from_ptr(player_name) {
+        Some(s) => s,
+        None => return,
     };
 
-    let topic = format!("{prefix}/monitor/{player_name}/{event}");
+    mqtt::publish(&player_name, event, payload, fields, retain);
+}
 
-    let mut client = mqtt::CLIENT.lock().unwrap();
-    match client.as_mut() {
-        Some(client) => {
-            if let Err(e) = client.try_publish(topic, QoS::AtLeastOnce, false, payload) {
-                error!("{}", e);
-            }
-        }
-        None => {
-            warn!("No MQTT connection yet.");
-        }
-    }
+fn update_state(player_name: *const c_char, update: impl FnOnce(&mut state::PlayerState)) {
+    let player_name = match str_from_ptr(player_name) {
+        Some(s) => s,
+        None => return,
+    };
+
+    state::update(&player_name, update);
 }
 
 #[no_mangle]
 pub extern "C" fn mstarPlayingStateChanged(player_name: *const c_char, is_playing: bool) {
-    debug!("mstarPlayingStateChanged");
+    catch_panic("mstarPlayingStateChanged", || {
+        debug!("mstarPlayingStateChanged");
+
+        if !is_playing {
+            // The last position might have been held back by the rate limiting.
+            if let Some(position) =
+                str_from_ptr(player_name).and_then(|s| throttle::take_pending(&s))
+            {
+                publish_position(player_name, position);
+            }
+        }
 
-    let state = if is_playing { "playing" } else { "stopped" };
-    publish(player_name, state, Vec::new());
+        let state = if is_playing { "playing" } else { "stopped" };
+        publish(player_name, state, Vec::new(), json!({}), false);
+        update_state(player_name, |state| state.playing = is_playing);
+    });
 }
 
 #[no_mangle]
 pub extern "C" fn mstarNextEntrySelected(player_name: *const c_char) {
-    debug!("mstarNextEntrySelected");
+    catch_panic("mstarNextEntrySelected", || {
+        debug!("mstarNextEntrySelected");
 
-    publish(player_name, "next", Vec::new());
+        publish(player_name, "next", Vec::new(), json!({}), false);
+    });
 }
 
 #[no_mangle]
 pub extern "C" fn mstarPreviousEntrySelected(player_name: *const c_char) {
-    debug!("mstarPreviousEntrySelected");
+    catch_panic("mstarPreviousEntrySelected", || {
+        debug!("mstarPreviousEntrySelected");
 
-    publish(player_name, "previous", Vec::new());
+        publish(player_name, "previous", Vec::new(), json!({}), false);
+    });
 }
 
 #[no_mangle]
 pub extern "C" fn mstarPlaylistEntrySelected(
-    _player_name: *const c_char,
-    _playlist_index: c_int,
-    _playlist_entry_name: *const c_char,
-    _duration: c_double,
+    player_name: *const c_char,
+    playlist_index: c_int,
+    playlist_entry_name: *const c_char,
+    duration: c_double,
 ) {
-    debug!("mstarPlaylistEntrySelected");
+    catch_panic("mstarPlaylistEntrySelected", || {
+        debug!("mstarPlaylistEntrySelected");
+
+        let playlist_entry_name = match str_from_ptr(playlist_entry_name) {
+            Some(s) => s,
+            None => return,
+        };
+
+        let payload = format!("{playlist_index}\n{playlist_entry_name}\n{duration}").into_bytes();
+        let fields = json!({
+            "index": playlist_index,
+            "name": playlist_entry_name,
+            "duration": duration,
+        });
+        publish(player_name, "entry", payload, fields, false);
+        update_state(player_name, |state| {
+            state.entry = Some(state::Entry {
+                index: playlist_index,
+                name: playlist_entry_name.into(),
+                duration,
+            })
+        });
+    });
 }
 
 #[no_mangle]
 pub extern "C" fn mstarPlaylistEntryDurationChanged(
-    _player_name: *const c_char,
-    _playlist_index: c_int,
-    _duration: c_double,
+    player_name: *const c_char,
+    playlist_index: c_int,
+    duration: c_double,
 ) {
-    debug!("mstarPlaylistEntryDurationChanged");
+    catch_panic("mstarPlaylistEntryDurationChanged", || {
+        debug!("mstarPlaylistEntryDurationChanged");
+
+        let event = format!("playlist/{playlist_index}/duration");
+        let payload = duration.to_string().into_bytes();
+        let fields = json!({ "index": playlist_index, "duration": duration });
+        publish(player_name, event.as_str(), payload, fields, true);
+        update_state(player_name, |state| {
+            if let Some(entry) = state.entry.as_mut().filter(|e| e.index == playlist_index) {
+                entry.duration = duration;
+            }
+        });
+    });
 }
 
 #[no_mangle]
 pub extern "C" fn mstarPlaylistEntryNameChanged(
-    _player_name: *const c_char,
-    _playlist_index: c_int,
-    _playlist_entry_name: *const c_char,
+    player_name: *const c_char,
+    playlist_index: c_int,
+    playlist_entry_name: *const c_char,
 ) {
-    debug!("mstarPlaylistEntryNameChanged");
+    catch_panic("mstarPlaylistEntryNameChanged", || {
+        debug!("mstarPlaylistEntryNameChanged");
+
+        let playlist_entry_name = match str_from_ptr(playlist_entry_name) {
+            Some(s) => s,
+            None => return,
+        };
+
+        let event = format!("playlist/{playlist_index}/name");
+        let payload = playlist_entry_name.as_bytes().to_vec();
+        let fields = json!({ "index": playlist_index, "name": playlist_entry_name });
+        publish(player_name, event.as_str(), payload, fields, true);
+        update_state(player_name, |state| {
+            if let Some(entry) = state.entry.as_mut().filter(|e| e.index == playlist_index) {
+                entry.name = playlist_entry_name.into();
+            }
+        });
+    });
 }
 
 #[no_mangle]
 pub extern "C" fn mstarTrackVolumeChanged(
-    _player_name: *const c_char,
-    _track_name: *const c_char,
-    _volume: c_double,
+    player_name: *const c_char,
+    track_name: *const c_char,
+    volume: c_double,
 ) {
-    debug!("mstarTrackVolumeChanged");
+    catch_panic("mstarTrackVolumeChanged", || {
+        debug!("mstarTrackVolumeChanged");
+
+        let track_name = match str_from_ptr(track_name) {
+            Some(s) => s,
+            None => return,
+        };
+
+        let event = topic::Event {
+            name: "volume",
+            track: Some(&track_name),
+        };
+        let payload = volume.to_string().into_bytes();
+        let fields = json!({ "track": track_name, "volume": volume });
+        publish(player_name, event, payload, fields, false);
+        update_state(player_name, |state| {
+            state.track_volumes.insert(track_name.into(), volume);
+        });
+    });
 }
 
 #[no_mangle]
 pub extern "C" fn mstarPositionChanged(player_name: *const c_char, position: c_double) {
-    debug!("mstarPositionChanged");
+    catch_panic("mstarPositionChanged", || {
+        debug!("mstarPositionChanged");
+
+        let limits = match sync::read(&CONFIG).as_ref() {
+            Some(config) => throttle::Limits {
+                min_interval: Duration::from_millis(config.position_interval.into()),
+                min_delta: f64::from(config.position_min_delta) / 1000.0,
+            },
+            None => return,
+        };
+
+        let player_name_str = match str_from_ptr(player_name) {
+            Some(s) => s,
+            None => return,
+        };
+        if !throttle::report(&player_name_str, position, &limits) {
+            return;
+        }
+
+        publish_position(player_name, position);
+    });
+}
 
+fn publish_position(player_name: *const c_char, position: c_double) {
     let payload = position.to_string().into_bytes();
-    publish(player_name, "position", payload);
+    let fields = json!({ "position": position });
+    publish(player_name, "position", payload, fields, false);
+    update_state(player_name, |state| state.position = position);
 }
 
 slint::slint! {
-    import { LineEdit, SpinBox, StandardButton, VerticalBox, GroupBox } from "std-widgets.slint";
+    import { Button, CheckBox, ComboBox, LineEdit, SpinBox, StandardButton, TextEdit, VerticalBox, GroupBox } from "std-widgets.slint";
+
+    // Problem with a setting, with an empty message if there's none.
+    export struct Problem {
+        message: string,
+        // Whether the settings can't be applied, otherwise it's just a warning.
+        blocking: bool,
+    }
+
+    export struct ConnectionStatus {
+        state: string,
+        broker: string,
+        connected-for: string,
+        reconnects: string,
+        published: string,
+        received: string,
+        last-error: string,
+    }
+
     export component MainWindow inherits Window {
+        // Status of the running connection, refreshed while the window is open.
+        in property<ConnectionStatus> status;
+
         in property<string> default-server;
         in-out property<string> server <=> server-edit.text;
+        pure callback check-server(string) -> Problem;
+        property<Problem> server-problem: root.check-server(server-edit.text);
 
         in-out property<int> port <=> portEdit.value;
 
         in property<string> default-client-name;
         in-out property<string> client-name <=> client-name-edit.text;
+        pure callback check-client-name(string) -> Problem;
+        property<Problem> client-name-problem: root.check-client-name(client-name-edit.text);
 
         in property<string> default-topic-prefix;
         in-out property<string> topic-prefix <=> topic-prefix-edit.text;
+        // Return a description of the problem or an empty string if the setting is valid.
+        pure callback check-topic-prefix(string) -> string;
+
+        in-out property<bool> case-insensitive-player-names <=> case-insensitive-player-names-box.checked;
+
+        in property<string> default-monitor-topic-template;
+        in-out property<string> monitor-topic-template <=> monitor-topic-template-edit.text;
+        in property<string> default-control-topic-template;
+        in-out property<string> control-topic-template <=> control-topic-template-edit.text;
+        // Return a description of the problem or an empty string if the template is valid.
+        pure callback check-monitor-topic-template(string) -> string;
+        pure callback check-control-topic-template(string) -> string;
+        // Check that the templates don't overlap, given the topic prefix and both templates.
+        pure callback check-topic-templates(string, string, string) -> string;
+
+        in-out property<string> aliases <=> aliases-edit.text;
+        pure callback check-aliases(string) -> string;
+
+        in-out property<int> protocol-version <=> protocol-version-box.current-index;
+        in-out property<int> payload-format <=> payload-format-box.current-index;
+
+        in-out property<int> position-interval <=> position-interval-edit.value;
+        in-out property<int> position-min-delta <=> position-min-delta-edit.value;
+        in-out property<int> position-expiry <=> position-expiry-edit.value;
+
+        in-out property<int> reconnect-delay <=> reconnect-delay-edit.value;
+        in-out property<int> reconnect-max-delay <=> reconnect-max-delay-edit.value;
+        in-out property<int> reconnect-jitter <=> reconnect-jitter-edit.value;
+        in-out property<int> reconnect-attempts <=> reconnect-attempts-edit.value;
+        pure callback check-reconnect-delays(int, int) -> string;
+
+        in-out property<string> username <=> username-edit.text;
+        in-out property<string> password <=> password-edit.text;
+
+        in-out property<bool> tls <=> tls-box.checked;
+        in-out property<string> tls-ca-file <=> tls-ca-file-edit.text;
+        in-out property<string> tls-client-certificate-file <=> tls-client-certificate-file-edit.text;
+        in-out property<string> tls-client-key-file <=> tls-client-key-file-edit.text;
+        in-out property<bool> tls-skip-hostname-verification <=> tls-skip-hostname-verification-box.checked;
+
+        // Try the entered settings without touching the running connection.
+        callback test-connection();
+        in property<bool> testing;
+        in property<bool> test-succeeded;
+        in property<string> test-result;
 
         callback save();
         callback abort();
 
+        property<bool> valid: !server-problem.blocking &&
+            !client-name-problem.blocking &&
+            topic-prefix-error.text == "" &&
+            monitor-topic-template-error.text == "" &&
+            control-topic-template-error.text == "" &&
+            topic-templates-error.text == "" &&
+            aliases-error.text == "" &&
+            reconnect-error.text == "";
+
         title: "M*Player MQTT Plugin";
 
         VerticalBox {
@@ -176,12 +520,53 @@ slint::slint! {
                 horizontal-alignment: center;
             }
 
+            GroupBox {
+                title: "Connection Status";
+                GridLayout {
+                    spacing: 4px;
+                    Row {
+                        Text { text: "State:"; }
+                        Text { text: root.status.state; }
+                        Text { text: "Broker:"; }
+                        Text { text: root.status.broker; }
+                    }
+                    Row {
+                        Text { text: "Connected for:"; }
+                        Text { text: root.status.connected-for; }
+                        Text { text: "Reconnects:"; }
+                        Text { text: root.status.reconnects; }
+                    }
+                    Row {
+                        Text { text: "Published:"; }
+                        Text { text: root.status.published; }
+                        Text { text: "Received:"; }
+                        Text { text: root.status.received; }
+                    }
+                    Row {
+                        Text { text: "Last error:"; }
+                        Text {
+                            colspan: 3;
+                            text: root.status.last-error;
+                            wrap: word-wrap;
+                        }
+                    }
+                }
+            }
+
             HorizontalLayout {
                 GroupBox {
                     title: "Server";
                     horizontal-stretch: 1;
-                    server-edit := LineEdit {
-                        placeholder-text: root.default-server;
+                    VerticalLayout {
+                        spacing: 4px;
+                        server-edit := LineEdit {
+                            placeholder-text: root.default-server;
+                        }
+                        server-error := Text {
+                            text: root.server-problem.message;
+                            color: root.server-problem.blocking ? red : #c08000;
+                            visible: self.text != "";
+                        }
                     }
                 }
 
@@ -197,15 +582,233 @@ slint::slint! {
 
             GroupBox {
                 title: "Client Name";
-                client-name-edit := LineEdit {
-                    placeholder-text: root.default-client-name;
+                VerticalLayout {
+                    spacing: 4px;
+                    client-name-edit := LineEdit {
+                        placeholder-text: root.default-client-name;
+                    }
+                    client-name-error := Text {
+                        text: root.client-name-problem.message;
+                        color: root.client-name-problem.blocking ? red : #c08000;
+                        visible: self.text != "";
+                    }
                 }
             }
 
             GroupBox {
                 title: "Topic Prefix";
-                topic-prefix-edit := LineEdit {
-                    placeholder-text: root.default-topic-prefix;
+                VerticalLayout {
+                    spacing: 4px;
+                    topic-prefix-edit := LineEdit {
+                        placeholder-text: root.default-topic-prefix;
+                    }
+                    topic-prefix-error := Text {
+                        text: root.check-topic-prefix(topic-prefix-edit.text);
+                        color: red;
+                        visible: self.text != "";
+                    }
+                }
+            }
+
+            case-insensitive-player-names-box := CheckBox {
+                text: "Match player names of commands case-insensitively";
+            }
+
+            GroupBox {
+                title: "Topic Templates";
+                VerticalLayout {
+                    spacing: 4px;
+                    monitor-topic-template-edit := LineEdit {
+                        placeholder-text: root.default-monitor-topic-template;
+                    }
+                    monitor-topic-template-error := Text {
+                        text: root.check-monitor-topic-template(monitor-topic-template-edit.text);
+                        color: red;
+                        visible: self.text != "";
+                    }
+                    control-topic-template-edit := LineEdit {
+                        placeholder-text: root.default-control-topic-template;
+                    }
+                    control-topic-template-error := Text {
+                        text: root.check-control-topic-template(control-topic-template-edit.text);
+                        color: red;
+                        visible: self.text != "";
+                    }
+                    topic-templates-error := Text {
+                        text: root.check-topic-templates(
+                            topic-prefix-edit.text,
+                            monitor-topic-template-edit.text,
+                            control-topic-template-edit.text);
+                        color: red;
+                        visible: self.text != "";
+                    }
+                }
+            }
+
+            GroupBox {
+                title: "Player Aliases";
+                VerticalLayout {
+                    spacing: 4px;
+                    Text {
+                        text: "One alias per line, used in topics instead of the player name: <player name> = <alias>";
+                        wrap: word-wrap;
+                    }
+                    aliases-edit := TextEdit {
+                        min-height: 60px;
+                    }
+                    aliases-error := Text {
+                        text: root.check-aliases(aliases-edit.text);
+                        color: red;
+                        visible: self.text != "";
+                    }
+                }
+            }
+
+            HorizontalLayout {
+                GroupBox {
+                    title: "Username";
+                    username-edit := LineEdit {
+                        placeholder-text: "anonymous";
+                    }
+                }
+
+                GroupBox {
+                    title: "Password";
+                    password-edit := LineEdit {
+                        input-type: InputType.password;
+                    }
+                }
+            }
+
+            HorizontalLayout {
+                GroupBox {
+                    title: "Protocol";
+                    horizontal-stretch: 1;
+                    protocol-version-box := ComboBox {
+                        model: ["MQTT 3.1.1", "MQTT 5"];
+                    }
+                }
+
+                GroupBox {
+                    title: "Payload Format";
+                    horizontal-stretch: 1;
+                    payload-format-box := ComboBox {
+                        model: ["Raw", "JSON"];
+                    }
+                }
+            }
+
+            HorizontalLayout {
+                GroupBox {
+                    title: "Position Interval (ms)";
+                    position-interval-edit := SpinBox {
+                        minimum: 0;
+                        maximum: 60000;
+                    }
+                }
+
+                GroupBox {
+                    title: "Position Change (ms)";
+                    position-min-delta-edit := SpinBox {
+                        minimum: 0;
+                        maximum: 60000;
+                    }
+                }
+
+                GroupBox {
+                    title: "Position Expiry (s)";
+                    position-expiry-edit := SpinBox {
+                        enabled: protocol-version-box.current-index == 1;
+                        minimum: 0;
+                        maximum: 3600;
+                    }
+                }
+            }
+
+            GroupBox {
+                title: "TLS";
+                VerticalLayout {
+                    spacing: 4px;
+                    tls-box := CheckBox {
+                        text: "Use TLS";
+                    }
+                    tls-ca-file-edit := LineEdit {
+                        enabled: tls-box.checked;
+                        placeholder-text: "CA certificate file (platform certificates if empty)";
+                    }
+                    tls-client-certificate-file-edit := LineEdit {
+                        enabled: tls-box.checked;
+                        placeholder-text: "Client certificate file (optional)";
+                    }
+                    tls-client-key-file-edit := LineEdit {
+                        enabled: tls-box.checked;
+                        placeholder-text: "Client key file (optional)";
+                    }
+                    tls-skip-hostname-verification-box := CheckBox {
+                        enabled: tls-box.checked;
+                        text: "Skip hostname verification";
+                    }
+                }
+            }
+
+            GroupBox {
+                title: "Reconnecting";
+                VerticalLayout {
+                    spacing: 4px;
+                    HorizontalLayout {
+                        spacing: 8px;
+                        VerticalLayout {
+                            Text { text: "First delay (ms)"; }
+                            reconnect-delay-edit := SpinBox {
+                                minimum: 1;
+                                maximum: 600000;
+                            }
+                        }
+                        VerticalLayout {
+                            Text { text: "Maximum delay (ms)"; }
+                            reconnect-max-delay-edit := SpinBox {
+                                minimum: 1;
+                                maximum: 3600000;
+                            }
+                        }
+                        VerticalLayout {
+                            Text { text: "Jitter (%)"; }
+                            reconnect-jitter-edit := SpinBox {
+                                minimum: 0;
+                                maximum: 100;
+                            }
+                        }
+                        VerticalLayout {
+                            Text { text: "Give up after (0 = never)"; }
+                            reconnect-attempts-edit := SpinBox {
+                                minimum: 0;
+                                maximum: 1000;
+                            }
+                        }
+                    }
+                    reconnect-error := Text {
+                        text: root.check-reconnect-delays(reconnect-delay-edit.value, reconnect-max-delay-edit.value);
+                        color: red;
+                        visible: self.text != "";
+                    }
+                }
+            }
+
+            HorizontalLayout {
+                spacing: 8px;
+                Button {
+                    text: "Test Connection";
+                    enabled: root.valid && !root.testing;
+                    clicked => {
+                        root.test-connection();
+                    }
+                }
+                Text {
+                    text: root.test-result;
+                    color: root.testing ? #808080 : root.test-succeeded ? #008000 : red;
+                    vertical-alignment: center;
+                    wrap: word-wrap;
+                    horizontal-stretch: 1;
                 }
             }
 
@@ -213,9 +816,7 @@ slint::slint! {
                 alignment: center;
                 StandardButton {
                     kind: apply;
-                    enabled: server-edit.text != "" &&
-                        client-name-edit.text != "" &&
-                        topic-prefix-edit.text != "";
+                    enabled: root.valid;
                     clicked => {
                         root.save();
                     }
@@ -231,125 +832,357 @@ slint::slint! {
     }
 }
 
-#[no_mangle]
-pub extern "C" fn mstarConfigure() {
-    debug!("mstarConfigure");
-    let window = MainWindow::new().unwrap();
-
-    {
-        let default_config = Configuration::default();
-        let config = CONFIG.read().unwrap();
-        let config = config.as_ref().unwrap_or(&default_config);
-
-        window.set_server(config.server.clone().into());
-        window.set_port(config.port as i32);
-        window.set_client_name(config.client_name.clone().into());
-        window.set_topic_prefix(config.topic_prefix.clone().into());
-
-        window.set_default_server(default_config.server.into());
-        window.set_default_client_name(default_config.client_name.into());
-        window.set_default_topic_prefix(default_config.topic_prefix.into());
+/// Problem to show for the result of checking the server or client name `name`.
+fn name_problem(name: &str, result: Result<(), String>) -> Problem {
+    let blocking =
+        result.is_err() && validation::name_severity(name) == validation::Severity::Error;
+    Problem {
+        message: result.err().unwrap_or_default().into(),
+        blocking,
     }
+}
 
-    let weak = window.as_weak();
-    window.on_save(move || {
-        let window = weak.unwrap();
+/// Settings entered in the configuration window.
+fn config_from_window(window: &MainWindow) -> Configuration {
+    Configuration {
+        server: window.get_server().into(),
+        port: window.get_port() as u16,
+        client_name: window.get_client_name().into(),
+        topic_prefix: window.get_topic_prefix().into(),
+        payload_format: match window.get_payload_format() {
+            1 => PayloadFormat::Json,
+            _ => PayloadFormat::Raw,
+        },
+        position_interval: window.get_position_interval() as u32,
+        position_min_delta: window.get_position_min_delta() as u32,
+        username: window.get_username().into(),
+        password: window.get_password().into(),
+        tls: window.get_tls(),
+        tls_ca_file: window.get_tls_ca_file().into(),
+        tls_client_certificate_file: window.get_tls_client_certificate_file().into(),
+        tls_client_key_file: window.get_tls_client_key_file().into(),
+        tls_skip_hostname_verification: window.get_tls_skip_hostname_verification(),
+        protocol_version: match window.get_protocol_version() {
+            1 => ProtocolVersion::V5,
+            _ => ProtocolVersion::V311,
+        },
+        position_expiry: window.get_position_expiry() as u32,
+        reconnect_delay: window.get_reconnect_delay() as u32,
+        reconnect_max_delay: window.get_reconnect_max_delay() as u32,
+        reconnect_jitter: window.get_reconnect_jitter() as u32,
+        reconnect_attempts: window.get_reconnect_attempts() as u32,
+        case_insensitive_player_names: window.get_case_insensitive_player_names(),
+        monitor_topic_template: window
+            .get_monitor_topic_template()
+            .parse()
+            .unwrap_or_default(),
+        control_topic_template: window
+            .get_control_topic_template()
+            .parse()
+            .unwrap_or_default(),
+        aliases: aliases::Aliases::from_lines(&window.get_aliases()).unwrap_or_default(),
+        extra: sync::read(&CONFIG)
+            .as_ref()
+            .map(|config| config.extra.clone())
+            .unwrap_or_default(),
+    }
+}
 
-        let config = Configuration {
-            server: window.get_server().into(),
-            port: window.get_port() as u16,
-            client_name: window.get_client_name().into(),
-            topic_prefix: window.get_topic_prefix().into(),
+/// Show the current status of the connection in the configuration window.
+fn show_status(window: &MainWindow) {
+    let status = status::get();
+    window.set_status(ConnectionStatus {
+        state: status.state.as_str().into(),
+        connected_for: status
+            .connected_for()
+            .map(status::format_duration)
+            .unwrap_or_default()
+            .into(),
+        broker: status.broker.into(),
+        reconnects: status.reconnects.to_string().into(),
+        published: status.published.to_string().into(),
+        received: status.received.to_string().into(),
+        last_error: status.last_error.unwrap_or_default().into(),
+    });
+}
+
+fn hide(window: &MainWindow) {
+    if let Err(e) = window.hide() {
+        error!("Can't close configuration window: {e}");
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn mstarConfigure() {
+    catch_panic("mstarConfigure", || {
+        debug!("mstarConfigure");
+        let window = match MainWindow::new() {
+            Ok(window) => window,
+            Err(e) => {
+                error!("Can't open configuration window: {e}");
+                return;
+            }
         };
-        *CONFIG.write().unwrap() = Some(config);
-        mqtt::setup();
 
-        window.hide().unwrap();
-    });
+        {
+            let default_config = Configuration::default();
+            let config = sync::read(&CONFIG);
+            let config = config.as_ref().unwrap_or(&default_config);
+
+            window.set_server(config.server.clone().into());
+            window.set_port(config.port as i32);
+            window.set_client_name(config.client_name.clone().into());
+            window.set_topic_prefix(config.topic_prefix.clone().into());
+            window.set_case_insensitive_player_names(config.case_insensitive_player_names);
+            window.set_monitor_topic_template(config.monitor_topic_template.to_string().into());
+            window.set_control_topic_template(config.control_topic_template.to_string().into());
+            window.set_aliases(config.aliases.to_lines().into());
+            window.set_protocol_version(match config.protocol_version {
+                ProtocolVersion::V311 => 0,
+                ProtocolVersion::V5 => 1,
+            });
+            window.set_payload_format(match config.payload_format {
+                PayloadFormat::Raw => 0,
+                PayloadFormat::Json => 1,
+            });
+            window.set_position_interval(config.position_interval as i32);
+            window.set_position_min_delta(config.position_min_delta as i32);
+            window.set_position_expiry(config.position_expiry as i32);
+            window.set_reconnect_delay(config.reconnect_delay as i32);
+            window.set_reconnect_max_delay(config.reconnect_max_delay as i32);
+            window.set_reconnect_jitter(config.reconnect_jitter as i32);
+            window.set_reconnect_attempts(config.reconnect_attempts as i32);
+            window.set_username(config.username.clone().into());
+            window.set_password(config.password.clone().into());
+            window.set_tls(config.tls);
+            window.set_tls_ca_file(config.tls_ca_file.clone().into());
+            window
+                .set_tls_client_certificate_file(config.tls_client_certificate_file.clone().into());
+            window.set_tls_client_key_file(config.tls_client_key_file.clone().into());
+            window.set_tls_skip_hostname_verification(config.tls_skip_hostname_verification);
+
+            window.set_default_server(default_config.server.into());
+            window.set_default_client_name(default_config.client_name.into());
+            window.set_default_topic_prefix(default_config.topic_prefix.into());
+            window.set_default_monitor_topic_template(
+                default_config.monitor_topic_template.to_string().into(),
+            );
+            window.set_default_control_topic_template(
+                default_config.control_topic_template.to_string().into(),
+            );
+        }
+
+        window.on_check_server(|server| name_problem(&server, validation::check_server(&server)));
+        window.on_check_client_name(|client_name| {
+            name_problem(&client_name, validation::check_client_name(&client_name))
+        });
+        window.on_check_topic_prefix(|topic_prefix| {
+            match validation::check_topic_prefix(&topic_prefix) {
+                Ok(()) => "".into(),
+                Err(e) => e.into(),
+            }
+        });
+
+        window.on_check_reconnect_delays(
+            |delay, max_delay| match validation::check_reconnect_delays(
+                delay as u32,
+                max_delay as u32,
+            ) {
+                Ok(()) => "".into(),
+                Err(e) => e.into(),
+            },
+        );
+
+        window.on_check_monitor_topic_template(|template| {
+            match template.parse::<topic::MonitorTemplate>() {
+                Ok(_) => "".into(),
+                Err(e) => e.into(),
+            }
+        });
+        window.on_check_control_topic_template(|template| {
+            match template.parse::<topic::ControlTemplate>() {
+                Ok(_) => "".into(),
+                Err(e) => e.into(),
+            }
+        });
+
+        window.on_check_topic_templates(|topic_prefix, monitor, control| {
+            // Invalid templates are pointed out on their own.
+            let (Ok(monitor), Ok(control)) = (monitor.parse(), control.parse()) else {
+                return "".into();
+            };
+            match topic::check_templates(&topic_prefix, &monitor, &control) {
+                Ok(()) => "".into(),
+                Err(e) => e.into(),
+            }
+        });
+
+        window.on_check_aliases(|aliases| match aliases::Aliases::from_lines(&aliases) {
+            Ok(_) => "".into(),
+            Err(e) => e.into(),
+        });
+
+        let weak = window.as_weak();
+        window.on_save(move || {
+            let Some(window) = weak.upgrade() else {
+                return;
+            };
+
+            let config = config_from_window(&window);
+            *sync::write(&CONFIG) = Some(config);
+            mqtt::setup();
+
+            hide(&window);
+        });
+
+        let weak = window.as_weak();
+        window.on_test_connection(move || {
+            let Some(window) = weak.upgrade() else {
+                return;
+            };
+
+            let config = config_from_window(&window);
+            window.set_testing(true);
+            window.set_test_result("Testing connection…".into());
+
+            let weak = window.as_weak();
+            thread::spawn(move || {
+                let result = catch_panic("test_connection", || mqtt::test_connection(&config))
+                    .unwrap_or_else(|| Err("The test failed unexpectedly.".into()));
+                if let Err(e) = weak.upgrade_in_event_loop(move |window| {
+                    window.set_testing(false);
+                    window.set_test_succeeded(result.is_ok());
+                    window.set_test_result(match result {
+                        Ok(()) => "Connected, subscribed and received a test message.".into(),
+                        Err(e) => e.into(),
+                    });
+                }) {
+                    debug!("Not showing connection test result: {e}");
+                }
+            });
+        });
 
-    let weak = window.as_weak();
-    window.on_abort(move || weak.unwrap().hide().unwrap());
+        let weak = window.as_weak();
+        window.on_abort(move || {
+            if let Some(window) = weak.upgrade() {
+                hide(&window);
+            }
+        });
+
+        show_status(&window);
+        let status_timer = slint::Timer::default();
+        let weak = window.as_weak();
+        status_timer.start(
+            slint::TimerMode::Repeated,
+            Duration::from_secs(1),
+            move || {
+                if let Some(window) = weak.upgrade() {
+                    show_status(&window);
+                }
+            },
+        );
 
-    window.run().unwrap();
+        if let Err(e) = window.run() {
+            error!("Configuration window failed: {e}");
+        }
+    });
 }
 
 #[no_mangle]
 pub extern "C" fn mstarShutdown() {
-    debug!("mstarShutdown");
+    catch_panic("mstarShutdown", || {
+        debug!("mstarShutdown");
 
-    mqtt::teardown();
+        mqtt::teardown();
+    });
 }
 
 #[no_mangle]
 #[allow(clippy::missing_safety_doc)]
 pub unsafe extern "C" fn mstarLoadConfiguration(configuration_text: *const c_char) {
-    debug!("mstarLoadConfiguration");
+    catch_panic("mstarLoadConfiguration", || {
+        debug!("mstarLoadConfiguration");
 
-    let configuration_text = unsafe { CStr::from_ptr(configuration_text) };
-    let configuration_text = match configuration_text.to_str() {
-        Ok(s) => s,
-        Err(e) => {
-            error!("{}", e);
+        if configuration_text.is_null() {
+            error!("Received a null pointer instead of the configuration");
             return;
         }
-    };
-
-    if configuration_text.is_empty() {
-        warn!("Configuration was empty.");
-        mqtt::teardown();
-        *CONFIG.write().unwrap() = None;
-        return;
-    }
-
-    let parts: Vec<&str> = configuration_text.split('\n').collect();
-
-    if parts.len() != 4 {
-        error!("Malformed configuration.");
-        return;
-    }
+        let configuration_text = unsafe { CStr::from_ptr(configuration_text) };
+        let configuration_text = match configuration_text.to_str() {
+            Ok(s) => s,
+            Err(e) => {
+                error!("{}", e);
+                return;
+            }
+        };
 
-    let port: u16 = match parts[1].parse() {
-        Ok(port) => port,
-        Err(e) => {
-            error!("Malformed port in configuration: {e}");
+        if configuration_text.is_empty() {
+            warn!("Configuration was empty.");
+            mqtt::teardown();
+            *sync::write(&CONFIG) = None;
             return;
         }
-    };
 
-    *CONFIG.write().unwrap() = Some(Configuration {
-        server: parts[0].into(),
-        port,
-        client_name: parts[2].into(),
-        topic_prefix: parts[3].into(),
-    });
+        let config = match config::parse(configuration_text) {
+            Ok(config) => config,
+            Err(e) => {
+                error!("{e}");
+                return;
+            }
+        };
+        if let Err(errors) = validation::validate(&config) {
+            for e in &errors {
+                match e.severity {
+                    validation::Severity::Warning => warn!("{e}"),
+                    validation::Severity::Error => error!("{e}"),
+                }
+            }
+            if errors
+                .iter()
+                .any(|e| e.severity == validation::Severity::Error)
+            {
+                warn!("Keeping the previous configuration.");
+                return;
+            }
+        }
+
+        *sync::write(&CONFIG) = Some(config);
 
-    mqtt::setup();
+        mqtt::setup();
+    });
 }
 
 #[no_mangle]
 pub extern "C" fn mstarGetConfiguration() -> *const c_char {
-    debug!("mstarGetConfiguration");
-
-    let config = CONFIG.read().unwrap();
-    let configuration = match config.as_ref() {
-        None => String::new(),
-        Some(config) => {
-            format!(
-                "{}\n{}\n{}\n{}",
-                config.server, config.port, config.client_name, config.topic_prefix
-            )
-        }
-    };
+    catch_panic("mstarGetConfiguration", || {
+        debug!("mstarGetConfiguration");
 
-    CString::new(configuration).unwrap().into_raw()
+        let configuration = match sync::read(&CONFIG).as_ref() {
+            None => String::new(),
+            Some(config) => config::to_text(config),
+        };
+
+        CString::new(configuration)
+            .unwrap_or_else(|e| {
+                error!("Configuration contains a NUL character: {e}");
+                CString::default()
+            })
+            .into_raw()
+    })
+    .unwrap_or_else(|| CString::default().into_raw())
 }
 
 #[no_mangle]
 pub extern "C" fn mstarFreeConfigurationText(configuration_text: *const c_char) {
-    debug!("mstarFreeConfigurationText");
-    unsafe {
-        let _configuration = CString::from_raw(configuration_text as *mut i8);
-    }
+    catch_panic("mstarFreeConfigurationText", || {
+        debug!("mstarFreeConfigurationText");
+        if configuration_text.is_null() {
+            return;
+        }
+        unsafe {
+            let _configuration = CString::from_raw(configuration_text as *mut c_char);
+        }
+    });
 }
 
 #[cfg(test)]
@@ -362,8 +1195,93 @@ mod tests {
         assert_eq!(result, 2);
     }
 
+    #[test]
+    fn panics() {
+        assert_eq!(catch_panic("test", || 1), Some(1));
+        assert_eq!(
+            catch_panic("test", || -> i32 { panic!("injected panic") }),
+            None
+        );
+        let index = 2;
+        assert_eq!(
+            catch_panic("test", || -> i32 { panic!("injected panic {index}") }),
+            None
+        );
+    }
+
+    #[test]
+    fn poisoned_configuration() {
+        let result = std::thread::spawn(|| {
+            let _config = CONFIG.write().unwrap();
+            panic!("injected panic");
+        })
+        .join();
+        assert!(result.is_err());
+
+        // The exports keep working with the configuration as it was.
+        let player_name = CString::new("Main").unwrap();
+        mstarPositionChanged(player_name.as_ptr(), 1.0);
+        let configuration_raw = mstarGetConfiguration();
+        assert!(!configuration_raw.is_null());
+        mstarFreeConfigurationText(configuration_raw);
+        assert!(!CONFIG.is_poisoned());
+    }
+
+    #[test]
+    fn name_problems() {
+        // The dialog treats names like loading a project does.
+        let problem = name_problem("mqtt broker", validation::check_server("mqtt broker"));
+        assert!(!problem.message.is_empty());
+        assert!(!problem.blocking);
+        assert!(name_problem("", validation::check_client_name("")).blocking);
+        assert_eq!(name_problem("broker", Ok(())), Problem::default());
+    }
+
     #[test]
     fn configuration() {
+        let input_configuration = json!({
+            "version": 1,
+            "server": "127.0.0.1",
+            "port": 1,
+            "client_name": "client",
+            "topic_prefix": "topic",
+            "payload_format": "json",
+            "position_interval": 250,
+            "position_min_delta": 100,
+            "tls": true,
+            "tls_ca_file": "ca.pem",
+            "tls_client_certificate_file": "client.pem",
+            "tls_client_key_file": "client.key",
+            "tls_skip_hostname_verification": true,
+            "username": "user",
+            "password": "secret",
+            "protocol_version": "5",
+            "position_expiry": 10,
+            "case_insensitive_player_names": true,
+            "monitor_topic_template": "site/{player}/state/{track}/{event}",
+            "control_topic_template": "site/{player}/set/{command}",
+            "aliases": { "Main Stage": "main" },
+            "reconnect_delay": 500,
+            "reconnect_max_delay": 30000,
+            "reconnect_jitter": 50,
+            "reconnect_attempts": 10,
+            // Settings of newer versions are kept.
+            "future": [1, 2],
+        });
+        let input_configuration_raw = CString::new(input_configuration.to_string()).unwrap();
+        unsafe {
+            mstarLoadConfiguration(input_configuration_raw.as_ptr());
+        }
+
+        let configuration_raw = mstarGetConfiguration();
+        let configuration = unsafe { CStr::from_ptr(configuration_raw) };
+        let configuration: serde_json::Value =
+            serde_json::from_slice(configuration.to_bytes()).unwrap();
+        assert_eq!(configuration, input_configuration);
+        mstarFreeConfigurationText(configuration_raw);
+
+        // Configurations of older versions are migrated, getting the defaults for the missing
+        // fields.
         let input_configuration = "127.0.0.1\n1\nclient\ntopic";
         let input_configuration_raw = CString::new(input_configuration).unwrap();
         unsafe {
@@ -372,7 +1290,72 @@ mod tests {
 
         let configuration_raw = mstarGetConfiguration();
         let configuration = unsafe { CStr::from_ptr(configuration_raw) };
-        assert_eq!(configuration.to_str().unwrap(), input_configuration);
+        let configuration: serde_json::Value =
+            serde_json::from_slice(configuration.to_bytes()).unwrap();
+        assert_eq!(
+            configuration,
+            json!({
+                "version": 1,
+                "server": "127.0.0.1",
+                "port": 1,
+                "client_name": "client",
+                "topic_prefix": "topic",
+                "payload_format": "raw",
+                "position_interval": 0,
+                "position_min_delta": 0,
+                "tls": false,
+                "tls_ca_file": "",
+                "tls_client_certificate_file": "",
+                "tls_client_key_file": "",
+                "tls_skip_hostname_verification": false,
+                "username": "",
+                "password": "",
+                "protocol_version": "3.1.1",
+                "position_expiry": 0,
+                "case_insensitive_player_names": false,
+                "monitor_topic_template": "{prefix}/monitor/{player}/{event}",
+                "control_topic_template": "{prefix}/control/{player}/{command}",
+                "aliases": {},
+                "reconnect_delay": 1000,
+                "reconnect_max_delay": 60000,
+                "reconnect_jitter": 20,
+                "reconnect_attempts": 0,
+            })
+        );
+        mstarFreeConfigurationText(configuration_raw);
+
+        // Invalid configurations are rejected, keeping the previous one.
+        let input_configuration = r#"{"version":1,"server":"broker local","port":0}"#;
+        let input_configuration_raw = CString::new(input_configuration).unwrap();
+        unsafe {
+            mstarLoadConfiguration(input_configuration_raw.as_ptr());
+        }
+
+        let configuration_raw = mstarGetConfiguration();
+        let configuration = unsafe { CStr::from_ptr(configuration_raw) };
+        let configuration: serde_json::Value =
+            serde_json::from_slice(configuration.to_bytes()).unwrap();
+        assert_eq!(configuration["server"], "127.0.0.1");
+        assert_eq!(configuration["port"], 1);
+        mstarFreeConfigurationText(configuration_raw);
+
+        // Names the configuration window doesn't allow only cause warnings, the broker might
+        // accept them.
+        let input_configuration = "mqtt_broker.local\n1\nMStarPlayer on the main stage.v2\ntopic";
+        let input_configuration_raw = CString::new(input_configuration).unwrap();
+        unsafe {
+            mstarLoadConfiguration(input_configuration_raw.as_ptr());
+        }
+
+        let configuration_raw = mstarGetConfiguration();
+        let configuration = unsafe { CStr::from_ptr(configuration_raw) };
+        let configuration: serde_json::Value =
+            serde_json::from_slice(configuration.to_bytes()).unwrap();
+        assert_eq!(configuration["server"], "mqtt_broker.local");
+        assert_eq!(
+            configuration["client_name"],
+            "MStarPlayer on the main stage.v2"
+        );
         mstarFreeConfigurationText(configuration_raw);
     }
 }
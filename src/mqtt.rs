@@ -1,84 +1,839 @@
-use log::{debug, error, warn};
-use rumqttc::{Client, MqttOptions, Publish, QoS};
-use std::ffi::CString;
-use std::sync::Mutex;
+use core::ffi::{c_char, c_float, c_void};
+use log::{debug, error, info, warn};
+use rumqttc::v5;
+use rumqttc::v5::mqttbytes::v5::PublishProperties;
+use rumqttc::{Client, LastWill, MqttOptions, Publish, QoS, TlsConfiguration, Transport};
+use serde_json::{json, Map, Value};
+use std::ffi::{CStr, CString};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
 use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
-use crate::{CONFIG, INIT};
+use crate::plugin_interface_v2::Init;
+use crate::{
+    backoff, players, status, sync, text, tls, topic, validation, Configuration, PayloadFormat,
+    ProtocolVersion, CONFIG, INIT,
+};
 
-pub static CLIENT: Mutex<Option<Client>> = Mutex::new(None);
+/// Client of the configured MQTT protocol version.
+#[derive(Clone)]
+pub enum MqttClient {
+    V4(Client),
+    V5(v5::Client),
+}
+
+impl MqttClient {
+    /// Queue a message with QoS 1, the `properties` are only sent with MQTT 5.
+    fn try_publish(
+        &self,
+        topic: String,
+        retain: bool,
+        payload: impl Into<Vec<u8>>,
+        properties: PublishProperties,
+    ) -> Result<(), String> {
+        let payload = payload.into();
+        match self {
+            MqttClient::V4(client) => client
+                .try_publish(topic, QoS::AtLeastOnce, retain, payload)
+                .map_err(|e| e.to_string()),
+            MqttClient::V5(client) => client
+                .try_publish_with_properties(
+                    topic,
+                    v5::mqttbytes::QoS::AtLeastOnce,
+                    retain,
+                    payload,
+                    properties,
+                )
+                .map_err(|e| e.to_string()),
+        }
+    }
+
+    /// Queue a subscription without waiting if the queue is full, which would block the connection
+    /// thread for good.
+    fn try_subscribe(&self, topic: String) -> Result<(), String> {
+        match self {
+            MqttClient::V4(client) => client
+                .try_subscribe(topic, QoS::AtMostOnce)
+                .map_err(|e| e.to_string()),
+            MqttClient::V5(client) => client
+                .try_subscribe(topic, v5::mqttbytes::QoS::AtMostOnce)
+                .map_err(|e| e.to_string()),
+        }
+    }
+
+    fn disconnect(&self) -> Result<(), String> {
+        match self {
+            MqttClient::V4(client) => client.disconnect().map_err(|e| e.to_string()),
+            MqttClient::V5(client) => client.disconnect().map_err(|e| e.to_string()),
+        }
+    }
+}
+
+/// Control message received with either protocol version.
+struct Message {
+    topic: String,
+    payload: Vec<u8>,
+    /// Topic the sender expects the reply on, only set with MQTT 5.
+    response_topic: Option<String>,
+    /// Data to hand back with the reply so the sender can match it to its request.
+    correlation_data: Option<Vec<u8>>,
+}
+
+impl From<Publish> for Message {
+    fn from(p: Publish) -> Self {
+        Message {
+            topic: p.topic,
+            payload: p.payload.to_vec(),
+            response_topic: None,
+            correlation_data: None,
+        }
+    }
+}
+
+impl TryFrom<v5::mqttbytes::v5::Publish> for Message {
+    type Error = String;
+
+    fn try_from(p: v5::mqttbytes::v5::Publish) -> Result<Self, Self::Error> {
+        let topic = String::from_utf8(p.topic.to_vec())
+            .map_err(|e| format!("Received topic which is invalid ({e})"))?;
+        let properties = p.properties.unwrap_or_default();
+        Ok(Message {
+            topic,
+            payload: p.payload.to_vec(),
+            response_topic: properties.response_topic,
+            correlation_data: properties.correlation_data.map(|data| data.to_vec()),
+        })
+    }
+}
+
+/// Events of the connection the plugin reacts on.
+enum Notification {
+    Message(Message),
+    /// A message was handed to the broker.
+    Published,
+    Connected,
+    /// The broker answered a subscription, with the reason if it was rejected.
+    Subscribed(Result<(), String>),
+    Disconnected,
+    Error(String),
+}
+
+impl Notification {
+    fn from_v4(event: Result<rumqttc::Event, rumqttc::ConnectionError>) -> Option<Self> {
+        match event {
+            Ok(rumqttc::Event::Incoming(rumqttc::Incoming::Publish(p))) => {
+                Some(Notification::Message(p.into()))
+            }
+            Ok(rumqttc::Event::Incoming(rumqttc::Incoming::ConnAck(connack))) => {
+                debug!("Notification = {connack:?}");
+                Some(Notification::Connected)
+            }
+            Ok(rumqttc::Event::Incoming(rumqttc::Incoming::SubAck(suback))) => {
+                debug!("Notification = {suback:?}");
+                let rejected = suback
+                    .return_codes
+                    .iter()
+                    .find(|code| !matches!(code, rumqttc::SubscribeReasonCode::Success(_)));
+                Some(Notification::Subscribed(match rejected {
+                    Some(code) => Err(format!("{code:?}")),
+                    None => Ok(()),
+                }))
+            }
+            Ok(rumqttc::Event::Outgoing(rumqttc::Outgoing::Publish(_))) => {
+                Some(Notification::Published)
+            }
+            Ok(rumqttc::Event::Outgoing(rumqttc::Outgoing::Disconnect)) => {
+                Some(Notification::Disconnected)
+            }
+            Ok(notify) => {
+                debug!("Notification = {notify:?}");
+                None
+            }
+            Err(error) => Some(Notification::Error(error.to_string())),
+        }
+    }
+
+    fn from_v5(event: Result<v5::Event, v5::ConnectionError>) -> Option<Self> {
+        match event {
+            Ok(v5::Event::Incoming(v5::Incoming::Publish(p))) => match p.try_into() {
+                Ok(message) => Some(Notification::Message(message)),
+                Err(e) => {
+                    error!("{}", e);
+                    None
+                }
+            },
+            Ok(v5::Event::Incoming(v5::Incoming::ConnAck(connack))) => {
+                debug!("Notification = {connack:?}");
+                Some(Notification::Connected)
+            }
+            Ok(v5::Event::Incoming(v5::Incoming::SubAck(suback))) => {
+                debug!("Notification = {suback:?}");
+                let rejected = suback.return_codes.iter().find(|code| {
+                    !matches!(code, v5::mqttbytes::v5::SubscribeReasonCode::Success(_))
+                });
+                Some(Notification::Subscribed(match rejected {
+                    Some(code) => Err(format!("{code:?}")),
+                    None => Ok(()),
+                }))
+            }
+            Ok(v5::Event::Outgoing(rumqttc::Outgoing::Publish(_))) => Some(Notification::Published),
+            Ok(v5::Event::Outgoing(rumqttc::Outgoing::Disconnect)) => {
+                Some(Notification::Disconnected)
+            }
+            Ok(notify) => {
+                debug!("Notification = {notify:?}");
+                None
+            }
+            Err(error) => Some(Notification::Error(error.to_string())),
+        }
+    }
+}
+
+/// Connection of the configured MQTT protocol version.
+enum MqttConnection {
+    V4(Box<rumqttc::Connection>),
+    V5(Box<v5::Connection>),
+}
+
+impl MqttConnection {
+    /// Iterate over the events of the connection, logging and skipping the irrelevant ones.
+    fn notifications(&mut self) -> Box<dyn Iterator<Item = Notification> + '_> {
+        match self {
+            MqttConnection::V4(connection) => {
+                Box::new(connection.iter().filter_map(Notification::from_v4))
+            }
+            MqttConnection::V5(connection) => {
+                Box::new(connection.iter().filter_map(Notification::from_v5))
+            }
+        }
+    }
+
+    /// Wait for the next relevant event, `None` if there was none within `timeout`.
+    fn next_notification(&mut self, timeout: Duration) -> Option<Notification> {
+        let deadline = Instant::now() + timeout;
+        loop {
+            let remaining = deadline.checked_duration_since(Instant::now())?;
+            let notification = match self {
+                MqttConnection::V4(connection) => {
+                    Notification::from_v4(connection.recv_timeout(remaining).ok()?)
+                }
+                MqttConnection::V5(connection) => {
+                    Notification::from_v5(connection.recv_timeout(remaining).ok()?)
+                }
+            };
+            if notification.is_some() {
+                return notification;
+            }
+        }
+    }
+
+    /// Forget the broken network connection so that the next iteration reconnects.
+    fn clean(&mut self) {
+        match self {
+            MqttConnection::V4(connection) => connection.eventloop.clean(),
+            MqttConnection::V5(connection) => connection.eventloop.clean(),
+        }
+    }
+}
+
+pub static CLIENT: Mutex<Option<MqttClient>> = Mutex::new(None);
 static JOIN_HANDLE: Mutex<Option<thread::JoinHandle<()>>> = Mutex::new(None);
+/// Topic announcing whether the plugin is connected, kept for publishing `offline` on teardown.
+static AVAILABILITY_TOPIC: Mutex<Option<String>> = Mutex::new(None);
+/// Tells the connection thread to exit even if it can't reach the broker to disconnect.
+static STOP: AtomicBool = AtomicBool::new(false);
+/// When a failure to publish was last logged and how many failures weren't logged since.
+static PUBLISH_FAILURES: Mutex<(Option<Instant>, u64)> = Mutex::new((None, 0));
 
-fn handle_message(p: Publish) {
-    let topic_parts: Vec<&str> = p.topic.split('/').collect();
+/// Requests queued for the broker, which keeps the events of a short outage until it's back.
+const REQUEST_CAPACITY: usize = 1000;
+/// Minimum time between two logged failures to publish.
+const PUBLISH_FAILURE_LOG_INTERVAL: Duration = Duration::from_secs(10);
 
-    if topic_parts.len() != 4 {
-        warn!("Received malformed topic (require 4 levels): {}", p.topic);
-        return;
+/// Pseudo player name under which the plugin itself publishes and receives commands.
+pub const PLUGIN_PLAYER_NAME: &str = "_plugin";
+
+/// Build the payload of a monitor message in the configured format.
+///
+/// `payload` is used as-is for the raw format while `fields` are merged into the JSON object.
+fn format_payload(
+    format: PayloadFormat,
+    player_name: &str,
+    event: &str,
+    payload: Vec<u8>,
+    fields: Value,
+    timestamp: u64,
+) -> Vec<u8> {
+    match format {
+        PayloadFormat::Raw => payload,
+        PayloadFormat::Json => {
+            let mut message = Map::new();
+            message.insert("event".into(), event.into());
+            message.insert("player".into(), player_name.into());
+            message.insert("timestamp".into(), timestamp.into());
+            if let Value::Object(fields) = fields {
+                message.extend(fields);
+            }
+            Value::Object(message).to_string().into_bytes()
+        }
     }
+}
 
-    let received_prefix = topic_parts[0];
-    let received_scope = topic_parts[1];
-    let received_player_name = topic_parts[2];
-    let received_command = topic_parts[3];
+/// Milliseconds since the Unix epoch.
+fn timestamp() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or_default()
+}
 
-    {
-        let config = CONFIG.read().unwrap();
-        let expected_prefix = &config
-            .as_ref()
-            .expect("CONFIG should be set by mstarInit")
-            .topic_prefix;
-        if received_prefix != expected_prefix {
-            warn!(
-                "Received malformed topic (expected '{}' prefix): {}",
-                expected_prefix, p.topic
-            );
+/// MQTT 5 properties identifying the player and event of a monitor message.
+fn monitor_properties(player_name: &str, event: &str) -> PublishProperties {
+    PublishProperties {
+        user_properties: vec![
+            ("player".into(), player_name.into()),
+            ("event".into(), event.into()),
+        ],
+        ..Default::default()
+    }
+}
+
+/// Topics an event is published on.
+enum Destination<'a> {
+    /// The monitor topic of the event.
+    Monitor,
+    /// The monitor topic and the response topic of the request, if it has one.
+    MonitorAndResponse(&'a Message),
+    /// The response topic of the request, or the monitor topic if it has none.
+    ResponseOrMonitor(&'a Message),
+}
+
+/// Publish an event of a player on its monitor topic.
+pub fn publish<'a>(
+    player_name: &str,
+    event: impl Into<topic::Event<'a>>,
+    payload: Vec<u8>,
+    fields: Value,
+    retain: bool,
+) {
+    publish_to(
+        player_name,
+        event.into(),
+        payload,
+        fields,
+        retain,
+        Destination::Monitor,
+    );
+}
+
+/// Publish an event of a player, replying to a request if the `destination` says so.
+fn publish_to(
+    player_name: &str,
+    event: topic::Event,
+    payload: Vec<u8>,
+    fields: Value,
+    retain: bool,
+    destination: Destination,
+) {
+    let config = sync::read(&CONFIG);
+    let config = config.as_ref();
+    let config = match config {
+        Some(config) => config,
+        None => {
+            info!("Not publishing message since plugin wasn't configured yet.");
             return;
         }
+    };
+    let prefix = &config.topic_prefix;
+
+    let topic =
+        config
+            .monitor_topic_template
+            .topic(prefix, config.aliases.slug(player_name), &event);
+    let payload = format_payload(
+        config.payload_format,
+        player_name,
+        &event.path(),
+        payload,
+        fields,
+        timestamp(),
+    );
+
+    let mut properties = monitor_properties(player_name, &event.path());
+    // Positions are outdated quickly, so don't deliver them to clients reconnecting later.
+    if event.name == "position" && config.position_expiry > 0 {
+        properties.message_expiry_interval = Some(config.position_expiry);
     }
 
-    {
-        let expected_scope = "control";
-        if received_scope != expected_scope {
-            warn!(
-                "Received malformed topic (expected '{}' scope): {}",
-                expected_scope, p.topic
+    let mut client = sync::lock(&CLIENT);
+    match client.as_mut() {
+        Some(client) => {
+            let (request, monitor) = match destination {
+                Destination::Monitor => (None, true),
+                Destination::MonitorAndResponse(request) => (Some(request), true),
+                Destination::ResponseOrMonitor(request) => {
+                    (Some(request), request.response_topic.is_none())
+                }
+            };
+            if let Some(request) = request {
+                if let Some(response_topic) = &request.response_topic {
+                    let properties = PublishProperties {
+                        correlation_data: request.correlation_data.clone().map(Into::into),
+                        ..properties.clone()
+                    };
+                    if let Err(e) = client.try_publish(
+                        response_topic.clone(),
+                        false,
+                        payload.clone(),
+                        properties,
+                    ) {
+                        publish_failed(&e);
+                    }
+                }
+            }
+            if monitor {
+                if let Err(e) = client.try_publish(topic, retain, payload, properties) {
+                    publish_failed(&e);
+                }
+            }
+        }
+        None => {
+            warn!("No MQTT connection yet.");
+        }
+    }
+}
+
+/// Log that a message couldn't be queued.
+///
+/// Once the queue is full because the broker is unreachable every event fails, so failures are
+/// only logged every now and then.
+fn publish_failed(error: &str) {
+    let mut failures = sync::lock(&PUBLISH_FAILURES);
+    let (logged, unlogged) = &mut *failures;
+    if logged.is_some_and(|logged| logged.elapsed() < PUBLISH_FAILURE_LOG_INTERVAL) {
+        *unlogged += 1;
+        return;
+    }
+    if *unlogged > 0 {
+        error!("{error} ({unlogged} more messages weren't published)");
+    } else {
+        error!("{error}");
+    }
+    *failures = (Some(Instant::now()), 0);
+}
+
+/// Publish the names of all players, one per line.
+fn list_players(init: &Init, request: &Message) {
+    let player_names: Vec<String> = players::refresh(init)
+        .into_iter()
+        .map(|player| player.name)
+        .collect();
+
+    let payload = player_names.join("\n").into_bytes();
+    let fields = json!({ "players": player_names });
+    publish_to(
+        PLUGIN_PLAYER_NAME,
+        "players".into(),
+        payload,
+        fields,
+        true,
+        Destination::MonitorAndResponse(request),
+    );
+}
+
+/// A track as reported by `Init::listTracks`.
+struct Track {
+    name: String,
+    data: String,
+    /// Encoding of the name, used when the name is passed back to M*Player.
+    encoding: text::Encoding,
+}
+
+/// Receives the tracks from `Init::listTracks`.
+///
+/// `user_data` must point to the `Vec<Track>` collecting the tracks.
+extern "C" fn collect_track(
+    track_name: *const c_char,
+    track_data: *const c_char,
+    user_data: *const c_void,
+) {
+    crate::catch_panic("collect_track", || {
+        let tracks = unsafe { &mut *(user_data as *mut Vec<Track>) };
+        match unsafe { (text::from_ptr(track_name), text::from_ptr(track_data)) } {
+            (Some((name, encoding)), Some((data, _))) => tracks.push(Track {
+                name: name.into(),
+                data: data.into(),
+                encoding,
+            }),
+            _ => error!("Track is a null pointer, skipping it."),
+        }
+    });
+}
+
+/// Publish the tracks of a player, one per line with name and data separated by a tab.
+fn list_tracks(init: &Init, player_name: &str, player_name_c: &CStr, request: &Message) {
+    let mut tracks: Vec<Track> = Vec::new();
+
+    (init.listTracks)(
+        player_name_c.as_ptr(),
+        collect_track,
+        &mut tracks as *mut Vec<Track> as *const c_void,
+    );
+
+    for track in &tracks {
+        players::remember_track(player_name, &track.name, track.encoding);
+    }
+
+    let payload = tracks
+        .iter()
+        .map(|track| format!("{}\t{}", track.name, track.data))
+        .collect::<Vec<_>>()
+        .join("\n");
+    let fields = json!({
+        "tracks": tracks
+            .iter()
+            .map(|track| json!({ "name": track.name, "data": track.data }))
+            .collect::<Vec<_>>(),
+    });
+    publish_to(
+        player_name,
+        "tracks".into(),
+        payload.into_bytes(),
+        fields,
+        true,
+        Destination::MonitorAndResponse(request),
+    );
+}
+
+/// Result of a control command, published on the `ack` topic.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Outcome {
+    Executed,
+    UnknownCommand,
+    UnknownPlayer,
+    /// The topic or payload couldn't be used.
+    Malformed,
+}
+
+impl Outcome {
+    fn as_str(self) -> &'static str {
+        match self {
+            Outcome::Executed => "executed",
+            Outcome::UnknownCommand => "unknown-command",
+            Outcome::UnknownPlayer => "unknown-player",
+            Outcome::Malformed => "malformed",
+        }
+    }
+}
+
+/// Fields of a command payload given as JSON object, empty for any other payload.
+fn payload_fields(payload: &[u8]) -> Map<String, Value> {
+    match serde_json::from_slice(payload) {
+        Ok(Value::Object(fields)) => fields,
+        _ => Map::new(),
+    }
+}
+
+/// Publish the outcome of a control command.
+///
+/// It's sent to the response topic of the command if it has one, otherwise to the `ack` topic of the player.
+fn acknowledge(p: &Message, player_name: &str, command: &str, outcome: Outcome) {
+    let correlation_id = payload_fields(&p.payload)
+        .get("correlation_id")
+        .and_then(Value::as_str)
+        .map(String::from);
+
+    let mut payload = format!("{command}\n{}", outcome.as_str());
+    if let Some(correlation_id) = &correlation_id {
+        payload.push('\n');
+        payload.push_str(correlation_id);
+    }
+    let fields = json!({
+        "command": command,
+        "outcome": outcome.as_str(),
+        "correlation_id": correlation_id,
+    });
+    publish_to(
+        player_name,
+        "ack".into(),
+        payload.into_bytes(),
+        fields,
+        false,
+        Destination::ResponseOrMonitor(p),
+    );
+}
+
+fn handle_plugin_message(init: &Init, p: &Message, command: &[&str]) -> Outcome {
+    match command {
+        ["list-players"] => list_players(init, p),
+        _ => {
+            warn!("Received topic with unknown plugin command: {}", p.topic);
+            return Outcome::UnknownCommand;
+        }
+    }
+
+    Outcome::Executed
+}
+
+/// Parse the payload of a track volume command, either a plain number or a JSON object with a `volume` field.
+///
+/// The error describes why the payload was rejected and is meant for logging.
+fn parse_volume(payload: &[u8]) -> Result<c_float, String> {
+    let volume: c_float = match payload_fields(payload).get("volume") {
+        Some(volume) => volume
+            .as_f64()
+            .ok_or_else(|| format!("non-numeric volume {volume}"))?
+            as c_float,
+        None => {
+            let text = std::str::from_utf8(payload)
+                .map_err(|e| format!("invalid ({e}) volume payload"))?
+                .trim();
+
+            text.parse()
+                .map_err(|e| format!("unparsable ({e}) volume payload '{text}'"))?
+        }
+    };
+
+    if !volume.is_finite() || volume < 0.0 {
+        return Err(format!("out-of-range volume {volume}"));
+    }
+
+    Ok(volume)
+}
+
+fn handle_message(p: Message) {
+    let received = {
+        let config = sync::read(&CONFIG);
+        let config = match config.as_ref() {
+            Some(config) => config,
+            None => {
+                info!("Ignoring message since plugin wasn't configured yet.");
+                return;
+            }
+        };
+        let received = match config
+            .control_topic_template
+            .parse(&config.topic_prefix, &p.topic)
+        {
+            Some(received) => received,
+            None => {
+                warn!(
+                    "Received topic not matching the control topic template '{}': {}",
+                    config.control_topic_template, p.topic
+                );
+                return;
+            }
+        };
+
+        // The topic might name the player by its alias.
+        let player_name = topic::unescape(received.player_name)
+            .map(|player_name| config.aliases.player_name(&player_name).to_string());
+
+        (received.player_name, player_name, received.command)
+    };
+    let (escaped_player_name, received_player_name, received_command) = received;
+    let received_command = received_command.as_slice();
+
+    let received_player_name = match received_player_name {
+        Ok(s) => s,
+        Err(e) => {
+            warn!("Received topic with {} as player name: {}", e, p.topic);
+            acknowledge(
+                &p,
+                escaped_player_name,
+                &received_command.join("/"),
+                Outcome::Malformed,
             );
             return;
         }
+    };
+
+    let outcome = execute(&p, &received_player_name, received_command);
+    acknowledge(
+        &p,
+        &received_player_name,
+        &received_command.join("/"),
+        outcome,
+    );
+}
+
+/// Execute the control command of the named player.
+fn execute(p: &Message, player_name: &str, command: &[&str]) -> Outcome {
+    let init = sync::read(&INIT);
+    let init = match init.as_ref() {
+        Some(init) => init,
+        None => {
+            // Without the functions of M*Player no player is known.
+            error!("Received command before mstarInit: {}", p.topic);
+            return Outcome::UnknownPlayer;
+        }
+    };
+
+    if player_name == PLUGIN_PLAYER_NAME {
+        return handle_plugin_message(init, p, command);
     }
 
-    let received_player_name = match CString::new(received_player_name) {
+    if let Err(e) = CString::new(player_name) {
+        error!(
+            "Received topic with invalid ({}) player name: {}",
+            e, p.topic
+        );
+        return Outcome::Malformed;
+    }
+
+    let case_insensitive = sync::read(&CONFIG)
+        .as_ref()
+        .is_some_and(|config| config.case_insensitive_player_names);
+    // Continue with M*Player's spelling in case the name only matched case-insensitively.
+    let player = match players::find(init, player_name, case_insensitive) {
+        Some(player) => player,
+        None => {
+            warn!(
+                "Received command for unknown player '{}': {}",
+                player_name, p.topic
+            );
+            return Outcome::UnknownPlayer;
+        }
+    };
+    let player_name = player.name.as_str();
+    let player_name_c = match text::encode(player_name, player.encoding) {
         Ok(s) => s,
         Err(e) => {
             error!(
                 "Received topic with invalid ({}) player name: {}",
                 e, p.topic
             );
-            return;
+            return Outcome::Malformed;
         }
     };
 
-    let init = INIT.read().unwrap();
-    let init = init.as_ref().expect("INIT should be set by mstarInit");
-
-    match received_command {
-        "play" => {
-            (init.play)(received_player_name.as_ptr());
+    match command {
+        ["play"] => {
+            (init.play)(player_name_c.as_ptr());
         }
-        "stop" => {
-            (init.stop)(received_player_name.as_ptr());
+        ["stop"] => {
+            (init.stop)(player_name_c.as_ptr());
         }
-        "next" => {
-            (init.next)(received_player_name.as_ptr());
+        ["next"] => {
+            (init.next)(player_name_c.as_ptr());
         }
-        "previous" => {
-            (init.previous)(received_player_name.as_ptr());
+        ["previous"] => {
+            (init.previous)(player_name_c.as_ptr());
+        }
+        ["list-tracks"] => {
+            list_tracks(init, player_name, &player_name_c, p);
+        }
+        ["track", received_track_name, "volume"] => {
+            let received_track_name = match topic::unescape(received_track_name)
+                .and_then(|s| text::encode(&s, players::track_encoding(&player, &s)))
+            {
+                Ok(s) => s,
+                Err(e) => {
+                    error!(
+                        "Received topic with invalid ({}) track name: {}",
+                        e, p.topic
+                    );
+                    return Outcome::Malformed;
+                }
+            };
+
+            let volume = match parse_volume(&p.payload) {
+                Ok(volume) => volume,
+                Err(e) => {
+                    warn!("Received {} for topic: {}", e, p.topic);
+                    return Outcome::Malformed;
+                }
+            };
+
+            (init.setTrackVolume)(player_name_c.as_ptr(), received_track_name.as_ptr(), volume);
         }
         _ => {
             warn!("Received topic with unknown command: {}", p.topic);
+            return Outcome::UnknownCommand;
         }
     }
+
+    Outcome::Executed
+}
+
+/// Create an MQTT 3.1.1 client which still has to be driven by iterating its connection.
+///
+/// The broker announces the plugin as offline on the `availability_topic` if the connection is
+/// lost.
+fn connect_v4(
+    config: &Configuration,
+    client_name: &str,
+    transport: Transport,
+    availability_topic: Option<&str>,
+) -> (MqttClient, MqttConnection) {
+    let mut options = MqttOptions::new(client_name, config.server.clone(), config.port);
+    options.set_transport(transport);
+    if !config.username.is_empty() {
+        options.set_credentials(config.username.clone(), config.password.clone());
+    }
+    if let Some(availability_topic) = availability_topic {
+        options.set_last_will(LastWill::new(
+            availability_topic,
+            "offline",
+            QoS::AtLeastOnce,
+            true,
+        ));
+    }
+
+    let (client, connection) = Client::new(options, REQUEST_CAPACITY);
+    (
+        MqttClient::V4(client),
+        MqttConnection::V4(Box::new(connection)),
+    )
+}
+
+/// Create an MQTT 5 client which still has to be driven by iterating its connection.
+fn connect_v5(
+    config: &Configuration,
+    client_name: &str,
+    transport: Transport,
+    availability_topic: Option<&str>,
+) -> (MqttClient, MqttConnection) {
+    let mut options = v5::MqttOptions::new(client_name, config.server.clone(), config.port);
+    options.set_transport(transport);
+    if !config.username.is_empty() {
+        options.set_credentials(config.username.clone(), config.password.clone());
+    }
+    if let Some(availability_topic) = availability_topic {
+        options.set_last_will(v5::mqttbytes::v5::LastWill::new(
+            availability_topic,
+            "offline",
+            v5::mqttbytes::QoS::AtLeastOnce,
+            true,
+            None,
+        ));
+    }
+
+    let (client, connection) = v5::Client::new(options, REQUEST_CAPACITY);
+    (
+        MqttClient::V5(client),
+        MqttConnection::V5(Box::new(connection)),
+    )
+}
+
+fn connect(
+    config: &Configuration,
+    client_name: &str,
+    availability_topic: Option<&str>,
+) -> Result<(MqttClient, MqttConnection), String> {
+    let transport = if config.tls {
+        let tls_config =
+            tls::client_config(config).map_err(|e| format!("Invalid TLS settings: {e}"))?;
+        Transport::Tls(TlsConfiguration::Rustls(Arc::new(tls_config)))
+    } else {
+        Transport::Tcp
+    };
+
+    Ok(match config.protocol_version {
+        ProtocolVersion::V311 => connect_v4(config, client_name, transport, availability_topic),
+        ProtocolVersion::V5 => connect_v5(config, client_name, transport, availability_topic),
+    })
 }
 
 /// (Re-)establish MQTT connection.
@@ -88,57 +843,302 @@ pub fn setup() {
 
     debug!("Setting up MQTT connection");
 
-    let config = CONFIG.read().unwrap();
-    let config = &config.as_ref().expect("CONFIG should be set by mstarInit");
-    let options = MqttOptions::new(
-        config.client_name.clone(),
-        config.server.clone(),
-        config.port,
+    // Players might have changed while the plugin wasn't configured.
+    players::invalidate();
+
+    let config = sync::read(&CONFIG);
+    let config = match config.as_ref() {
+        Some(config) => config,
+        None => {
+            warn!("Not connecting to MQTT broker since plugin wasn't configured yet.");
+            return;
+        }
+    };
+    let control_topic_filter = config.control_topic_template.filter(&config.topic_prefix);
+
+    let availability_topic = config.monitor_topic_template.topic(
+        &config.topic_prefix,
+        PLUGIN_PLAYER_NAME,
+        &"availability".into(),
     );
-    let topic_prefix = config.topic_prefix.clone();
+    status::set(status::Status::connecting(&config.server, config.port));
+    let (client, mut connection) =
+        match connect(config, &config.client_name, Some(&availability_topic)) {
+            Ok(connected) => connected,
+            Err(e) => {
+                error!("Not connecting to MQTT broker: {e}");
+                status::update(|status| {
+                    status.disconnected();
+                    status.failed(&e);
+                });
+                return;
+            }
+        };
 
-    let (client, mut connection) = Client::new(options, 10);
+    let mut backoff = backoff::Backoff::new(backoff::Policy {
+        initial_delay: Duration::from_millis(config.reconnect_delay.into()),
+        max_delay: Duration::from_millis(config.reconnect_max_delay.into()),
+        jitter: config.reconnect_jitter,
+        max_attempts: config.reconnect_attempts,
+    });
 
-    *CLIENT.lock().unwrap() = Some(client.clone());
-    *JOIN_HANDLE.lock().unwrap() = Some(thread::spawn(move || {
+    *sync::lock(&CLIENT) = Some(client.clone());
+    *sync::lock(&AVAILABILITY_TOPIC) = Some(availability_topic.clone());
+    STOP.store(false, Ordering::Relaxed);
+    *sync::lock(&JOIN_HANDLE) = Some(thread::spawn(move || {
         loop {
-            client
-                .subscribe(format!("{}/control/#", topic_prefix), QoS::AtMostOnce)
-                .unwrap();
-
-            for (i, notification) in connection.iter().enumerate() {
+            for notification in connection.notifications() {
                 match notification {
-                    Ok(rumqttc::Event::Incoming(rumqttc::Incoming::Publish(p))) => {
-                        handle_message(p);
+                    Notification::Message(message) => {
+                        status::update(|status| status.received += 1);
+                        // A single bad message mustn't stop the connection thread.
+                        crate::catch_panic("handle_message", || handle_message(message));
                     }
-                    Ok(rumqttc::Event::Outgoing(rumqttc::Outgoing::Disconnect)) => {
-                        return;
+                    Notification::Published => {
+                        status::update(|status| status.published += 1);
+                    }
+                    Notification::Connected => {
+                        let attempts = backoff.reset();
+                        if attempts > 0 {
+                            info!("Reconnected to MQTT broker after {attempts} attempts");
+                        }
+                        status::update(status::Status::connected);
+                        // The broker forgets the subscription with the session, so renew it with
+                        // every connection rather than queueing one per attempt.
+                        if let Err(e) = client.try_subscribe(control_topic_filter.clone()) {
+                            error!("{}", e);
+                        }
+                        if let Err(e) = client.try_publish(
+                            availability_topic.clone(),
+                            true,
+                            "online",
+                            monitor_properties(PLUGIN_PLAYER_NAME, "availability"),
+                        ) {
+                            error!("{}", e);
+                        }
+                        publish_connection(attempts, &status::get());
                     }
-                    Ok(notify) => {
-                        debug!("{i}. Notification = {notify:?}");
+                    Notification::Subscribed(Ok(())) => {}
+                    Notification::Subscribed(Err(reason)) => {
+                        let error = format!(
+                            "Broker rejected subscription to {control_topic_filter}: {reason}"
+                        );
+                        error!("{}", error);
+                        status::update(|status| status.failed(&error));
+                    }
+                    Notification::Disconnected => {
+                        return;
                     }
-                    Err(error) => {
-                        error!("{:#?}", error);
+                    Notification::Error(error) => {
+                        error!("{}", error);
+                        // The connection is down while waiting for the next attempt.
+                        status::update(|status| {
+                            status.failed(&error);
+                            status.reconnecting();
+                        });
                         break;
                     }
                 }
             }
-            // slow down error rate
-            std::thread::sleep(std::time::Duration::from_secs(1));
+            if STOP.load(Ordering::Relaxed) {
+                return;
+            }
 
-            connection.eventloop.clean();
+            let Some(delay) = backoff.failed() else {
+                let error = format!(
+                    "Gave up reconnecting to MQTT broker after {} attempts.",
+                    backoff.attempts() - 1
+                );
+                error!("{}", error);
+                status::update(|status| {
+                    status.disconnected();
+                    status.failed(&error);
+                });
+                // Nothing can be published without the connection thread.
+                *sync::lock(&CLIENT) = None;
+                return;
+            };
+            warn!(
+                "Reconnecting to MQTT broker in {:.1} s (attempt {})",
+                delay.as_secs_f64(),
+                backoff.attempts()
+            );
+            if !sleep_unless_stopped(delay) {
+                return;
+            }
+
+            connection.clean();
         }
     }));
 }
 
+/// Publish how the connection was established, retained so that subscribers see the latest.
+fn publish_connection(attempts: u32, status: &status::Status) {
+    let last_error = status.last_error.as_deref().unwrap_or_default();
+    publish(
+        PLUGIN_PLAYER_NAME,
+        "connection",
+        format!("{}\n{attempts}\n{last_error}", status.reconnects).into_bytes(),
+        json!({
+            "reconnects": status.reconnects,
+            "attempts": attempts,
+            "last_error": status.last_error,
+        }),
+        true,
+    );
+}
+
+/// Sleep for `duration` unless the connection is torn down in the meantime.
+///
+/// Returns whether the connection thread should go on.
+fn sleep_unless_stopped(duration: Duration) -> bool {
+    let deadline = Instant::now() + duration;
+    while let Some(remaining) = deadline.checked_duration_since(Instant::now()) {
+        if STOP.load(Ordering::Relaxed) {
+            return false;
+        }
+        thread::sleep(remaining.min(Duration::from_millis(100)));
+    }
+    !STOP.load(Ordering::Relaxed)
+}
+
+/// Time the broker gets to answer each step of a connection test.
+const TEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Wait until `check` accepts a notification, failing on connection errors.
+fn wait_for_step(
+    connection: &mut MqttConnection,
+    step: &str,
+    mut check: impl FnMut(Notification) -> Option<Result<(), String>>,
+) -> Result<(), String> {
+    loop {
+        match connection.next_notification(TEST_TIMEOUT) {
+            None => return Err(format!("No answer from the broker to the {step}.")),
+            Some(Notification::Error(e)) => return Err(format!("The {step} failed: {e}")),
+            Some(notification) => {
+                if let Some(result) = check(notification) {
+                    return result;
+                }
+            }
+        }
+    }
+}
+
+/// Connect, subscribe and receive a message of our own to check the settings.
+fn run_test(
+    client: &MqttClient,
+    connection: &mut MqttConnection,
+    topic: String,
+) -> Result<(), String> {
+    wait_for_step(
+        connection,
+        "connection",
+        |notification| match notification {
+            Notification::Connected => Some(Ok(())),
+            _ => None,
+        },
+    )?;
+
+    client.try_subscribe(topic.clone())?;
+    wait_for_step(
+        connection,
+        "subscription",
+        |notification| match notification {
+            Notification::Subscribed(result) => Some(
+                result.map_err(|reason| format!("The broker rejected the subscription: {reason}")),
+            ),
+            _ => None,
+        },
+    )?;
+
+    let token = timestamp().to_string();
+    client.try_publish(
+        topic.clone(),
+        false,
+        token.clone(),
+        PublishProperties::default(),
+    )?;
+    wait_for_step(
+        connection,
+        "test message",
+        |notification| match notification {
+            Notification::Message(message)
+                if message.topic == topic && message.payload == token.as_bytes() =>
+            {
+                Some(Ok(()))
+            }
+            _ => None,
+        },
+    )
+}
+
+/// Client name for testing the connection, which fits the length every broker accepts.
+///
+/// The broker would drop the running connection if its client name was used again.
+fn test_client_name(client_name: &str) -> String {
+    const SUFFIX: &str = "-test";
+    let mut base: String = client_name
+        .chars()
+        .take(validation::MAX_CLIENT_NAME_LENGTH - SUFFIX.len())
+        .collect();
+    if format!("{base}{SUFFIX}") == client_name {
+        base.pop();
+    }
+    format!("{base}{SUFFIX}")
+}
+
+/// Check the settings with a separate connection, leaving the running one untouched.
+///
+/// The error describes the step which failed and why.
+pub fn test_connection(config: &Configuration) -> Result<(), String> {
+    // Without a last will the test doesn't announce the plugin as offline.
+    let client_name = test_client_name(&config.client_name);
+    let (client, mut connection) = connect(config, &client_name, None)?;
+    let topic = config.monitor_topic_template.topic(
+        &config.topic_prefix,
+        PLUGIN_PLAYER_NAME,
+        &"connection-test".into(),
+    );
+
+    let result = run_test(&client, &mut connection, topic);
+
+    if client.disconnect().is_ok() {
+        // Send the disconnect before dropping the connection.
+        while let Some(notification) = connection.next_notification(TEST_TIMEOUT) {
+            if matches!(
+                notification,
+                Notification::Disconnected | Notification::Error(_)
+            ) {
+                break;
+            }
+        }
+    }
+    result
+}
+
 /// Gracefully end MQTT connection, if one exists.
 pub fn teardown() {
     debug!("Tearing down MQTT connection");
 
-    let mut client = CLIENT.lock().unwrap();
+    let mut client = sync::lock(&CLIENT);
+
+    STOP.store(true, Ordering::Relaxed);
+
+    let availability_topic = sync::lock(&AVAILABILITY_TOPIC).take();
 
     match client.as_mut() {
         Some(client) => {
+            // The last will is only sent by the broker if the connection is lost.
+            if let Some(availability_topic) = availability_topic {
+                if let Err(e) = client.try_publish(
+                    availability_topic,
+                    true,
+                    "offline",
+                    monitor_properties(PLUGIN_PLAYER_NAME, "availability"),
+                ) {
+                    error!("{}", e);
+                }
+            }
             if let Err(e) = client.disconnect() {
                 error!("{}", e);
             }
@@ -149,16 +1149,131 @@ pub fn teardown() {
         }
     }
 
-    // Destroy MQTT client.
+    // Destroy MQTT client. Release the lock as the connection thread might still try to publish.
     *client = None;
+    drop(client);
 
     // Wait for connection thread to exit.
-    let handle = JOIN_HANDLE
-        .lock()
-        .unwrap()
-        .take()
-        .expect("thread join handle should exist when client was created");
-    if handle.join().is_err() {
-        error!("error while joining MQTT connection thread");
+    match sync::lock(&JOIN_HANDLE).take() {
+        Some(handle) => {
+            if handle.join().is_err() {
+                error!("error while joining MQTT connection thread");
+            }
+        }
+        None => error!("MQTT connection thread is missing"),
+    }
+
+    status::update(status::Status::disconnected);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn payload_format() {
+        let raw = format_payload(
+            PayloadFormat::Raw,
+            "Player",
+            "position",
+            b"1.5".to_vec(),
+            json!({ "position": 1.5 }),
+            42,
+        );
+        assert_eq!(raw, b"1.5");
+
+        let payload = format_payload(
+            PayloadFormat::Json,
+            "Player",
+            "position",
+            b"1.5".to_vec(),
+            json!({ "position": 1.5 }),
+            42,
+        );
+        let payload: Value = serde_json::from_slice(&payload).unwrap();
+        assert_eq!(
+            payload,
+            json!({
+                "event": "position",
+                "player": "Player",
+                "timestamp": 42,
+                "position": 1.5,
+            })
+        );
+    }
+
+    #[test]
+    fn volume_payload() {
+        assert_eq!(parse_volume(b"0.5"), Ok(0.5));
+        assert_eq!(parse_volume(b" 1\n"), Ok(1.0));
+        assert_eq!(parse_volume(b"0"), Ok(0.0));
+        assert!(parse_volume(b"").is_err());
+        assert!(parse_volume(b"loud").is_err());
+        assert!(parse_volume(b"-0.1").is_err());
+        assert!(parse_volume(b"NaN").is_err());
+        assert!(parse_volume(b"1e40").is_err());
+        assert!(parse_volume(&[0xff]).is_err());
+
+        assert_eq!(
+            parse_volume(br#"{"volume":0.25,"correlation_id":"1"}"#),
+            Ok(0.25)
+        );
+        assert!(parse_volume(br#"{"volume":"loud"}"#).is_err());
+        assert!(parse_volume(br#"{"volume":-1}"#).is_err());
+    }
+
+    #[test]
+    fn command_payload_fields() {
+        let fields = payload_fields(br#"{"correlation_id":"42"}"#);
+        assert_eq!(fields["correlation_id"], "42");
+        assert!(payload_fields(b"0.5").is_empty());
+        assert!(payload_fields(b"").is_empty());
+    }
+
+    #[test]
+    fn failed_connection_test() {
+        let config = Configuration {
+            port: 1,
+            ..Default::default()
+        };
+        let error = test_connection(&config).unwrap_err();
+        assert!(error.starts_with("The connection failed: "), "{error}");
+
+        let config = Configuration {
+            tls: true,
+            tls_ca_file: "does-not-exist.pem".into(),
+            ..Default::default()
+        };
+        let error = test_connection(&config).unwrap_err();
+        assert!(error.contains("does-not-exist.pem"), "{error}");
+    }
+
+    #[test]
+    fn connection_test_client_name() {
+        assert_eq!(test_client_name("MStarPlayer"), "MStarPlayer-test");
+
+        let client_name = "MStarPlayer-main-stage1";
+        assert_eq!(client_name.len(), validation::MAX_CLIENT_NAME_LENGTH);
+        let test_name = test_client_name(client_name);
+        assert_eq!(test_name, "MStarPlayer-main-s-test");
+        assert!(validation::check_client_name(&test_name).is_ok());
+
+        // The test never takes over the client name of the running connection.
+        assert_eq!(
+            test_client_name("MStarPlayer-stage1-test"),
+            "MStarPlayer-stage-test"
+        );
+    }
+
+    /// Requires an MQTT broker on 127.0.0.1:1883.
+    #[test]
+    #[ignore]
+    fn connection_test() {
+        let config = Configuration {
+            client_name: "MStarPlayer-unit".into(),
+            topic_prefix: "unit-test".into(),
+            ..Default::default()
+        };
+        assert_eq!(test_connection(&config), Ok(()));
     }
 }
@@ -0,0 +1,159 @@
+//! Names of the players known to M*Player, used to validate control commands.
+
+use core::ffi::{c_char, c_void};
+use log::{debug, error};
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+
+use crate::plugin_interface_v2::Init;
+use crate::{sync, text};
+
+/// A player as reported by M*Player.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Player {
+    pub name: String,
+    /// Encoding of the name, used for the names passed back to M*Player for this player.
+    pub encoding: text::Encoding,
+}
+
+/// Players as last reported by `Init::listPlayers`, `None` if they need to be queried.
+static PLAYERS: Mutex<Option<Vec<Player>>> = Mutex::new(None);
+
+/// Encodings of the track names reported by `Init::listTracks`, by player and track name.
+static TRACK_ENCODINGS: Mutex<BTreeMap<(String, String), text::Encoding>> =
+    Mutex::new(BTreeMap::new());
+
+/// Receives the player names from `Init::listPlayers`.
+///
+/// `user_data` must point to the `Vec<Player>` collecting the players.
+extern "C" fn collect_player_name(player_name: *const c_char, user_data: *const c_void) {
+    crate::catch_panic("collect_player_name", || {
+        let players = unsafe { &mut *(user_data as *mut Vec<Player>) };
+        match unsafe { text::from_ptr(player_name) } {
+            Some((name, encoding)) => players.push(Player {
+                name: name.into(),
+                encoding,
+            }),
+            None => error!("Player name is a null pointer, skipping it."),
+        }
+    });
+}
+
+/// Query all players from M*Player and cache them.
+pub fn refresh(init: &Init) -> Vec<Player> {
+    let mut players: Vec<Player> = Vec::new();
+
+    (init.listPlayers)(
+        c"".as_ptr(),
+        collect_player_name,
+        &mut players as *mut Vec<Player> as *const c_void,
+    );
+    debug!("Known players: {players:?}");
+
+    *sync::lock(&PLAYERS) = Some(players.clone());
+    players
+}
+
+/// Forget the cached names so that they're queried again when needed.
+pub fn invalidate() {
+    *sync::lock(&PLAYERS) = None;
+}
+
+/// Remember the encoding M*Player used for the name of a track.
+pub fn remember_track(player_name: &str, track_name: &str, encoding: text::Encoding) {
+    sync::lock(&TRACK_ENCODINGS).insert((player_name.into(), track_name.into()), encoding);
+}
+
+/// Encoding to pass the name of a track of `player` back to M*Player.
+///
+/// Tracks which weren't listed yet are assumed to be encoded like the name of their player.
+pub fn track_encoding(player: &Player, track_name: &str) -> text::Encoding {
+    sync::lock(&TRACK_ENCODINGS)
+        .get(&(player.name.clone(), track_name.into()))
+        .copied()
+        .unwrap_or(player.encoding)
+}
+
+/// Find `name` in `players`, preferring an exact match over a case-insensitive one.
+fn find_in<'a>(players: &'a [Player], name: &str, case_insensitive: bool) -> Option<&'a Player> {
+    let exact = players.iter().find(|player| player.name == name);
+    let matching = || {
+        players
+            .iter()
+            .find(|player| player.name.to_lowercase() == name.to_lowercase())
+    };
+
+    match exact {
+        Some(player) => Some(player),
+        None if case_insensitive => matching(),
+        None => None,
+    }
+}
+
+/// Look up the player named in a control command.
+///
+/// The returned player carries the name M*Player knows it by.
+///
+/// The cached names are refreshed once if the player isn't found, since it might have been added
+/// since they were queried.
+pub fn find(init: &Init, name: &str, case_insensitive: bool) -> Option<Player> {
+    let cached = sync::lock(&PLAYERS).clone();
+    if let Some(player) = cached
+        .as_deref()
+        .and_then(|players| find_in(players, name, case_insensitive))
+    {
+        return Some(player.clone());
+    }
+
+    find_in(&refresh(init), name, case_insensitive).cloned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn matching() {
+        let players: Vec<Player> = ["Main", "main", "Jingles"]
+            .into_iter()
+            .map(|name| Player {
+                name: name.into(),
+                encoding: text::Encoding::Utf8,
+            })
+            .collect();
+        let find = |name, case_insensitive| {
+            find_in(&players, name, case_insensitive).map(|player| player.name.as_str())
+        };
+
+        assert_eq!(find("Main", false), Some("Main"));
+        assert_eq!(find("main", true), Some("main"));
+        assert_eq!(find("JINGLES", false), None);
+        assert_eq!(find("JINGLES", true), Some("Jingles"));
+        assert_eq!(find("Other", true), None);
+    }
+
+    #[test]
+    fn track_encodings() {
+        let player = Player {
+            name: "Track Encodings".into(),
+            encoding: text::Encoding::Utf8,
+        };
+        remember_track(&player.name, "Bühne", text::Encoding::Windows1252);
+        remember_track(&player.name, "Musik", text::Encoding::Utf8);
+
+        assert_eq!(
+            track_encoding(&player, "Bühne"),
+            text::Encoding::Windows1252
+        );
+        assert_eq!(track_encoding(&player, "Musik"), text::Encoding::Utf8);
+        assert_eq!(track_encoding(&player, "Other"), text::Encoding::Utf8);
+        let ansi_player = Player {
+            encoding: text::Encoding::Windows1252,
+            ..player
+        };
+        assert_eq!(
+            track_encoding(&ansi_player, "Other"),
+            text::Encoding::Windows1252
+        );
+    }
+}
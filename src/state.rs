@@ -0,0 +1,111 @@
+//! In-memory state of the players as reported by M*Player.
+
+use serde_json::{json, Value};
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+
+use crate::{mqtt, sync};
+
+/// Playlist entry currently selected in a player.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Entry {
+    pub index: i32,
+    pub name: String,
+    pub duration: f64,
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct PlayerState {
+    pub playing: bool,
+    pub entry: Option<Entry>,
+    pub position: f64,
+    pub track_volumes: BTreeMap<String, f64>,
+}
+
+impl PlayerState {
+    pub fn to_json(&self) -> Value {
+        json!({
+            "playing": self.playing,
+            "entry": self.entry.as_ref().map(|entry| json!({
+                "index": entry.index,
+                "name": entry.name,
+                "duration": entry.duration,
+            })),
+            "position": self.position,
+            "track_volumes": self.track_volumes,
+        })
+    }
+}
+
+static STATES: Mutex<BTreeMap<String, PlayerState>> = Mutex::new(BTreeMap::new());
+
+/// Apply `update` to the state of the named player.
+///
+/// Returns the new state if it changed.
+fn apply(player_name: &str, update: impl FnOnce(&mut PlayerState)) -> Option<PlayerState> {
+    let mut states = sync::lock(&STATES);
+    let state = states.entry(player_name.into()).or_default();
+
+    let previous = state.clone();
+    update(state);
+
+    if *state == previous {
+        None
+    } else {
+        Some(state.clone())
+    }
+}
+
+/// Apply `update` to the state of the named player and publish it if it changed.
+pub fn update(player_name: &str, update: impl FnOnce(&mut PlayerState)) {
+    if let Some(state) = apply(player_name, update) {
+        let fields = state.to_json();
+        let payload = fields.to_string().into_bytes();
+        mqtt::publish(player_name, "state", payload, fields, true);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn change_detection() {
+        let player_name = "change detection";
+
+        let state = apply(player_name, |state| state.playing = true).unwrap();
+        assert!(state.playing);
+
+        assert_eq!(apply(player_name, |state| state.playing = true), None);
+
+        let state = apply(player_name, |state| {
+            state.track_volumes.insert("Track".into(), 0.5);
+        })
+        .unwrap();
+        assert!(state.playing);
+        assert_eq!(state.track_volumes["Track"], 0.5);
+    }
+
+    #[test]
+    fn json() {
+        let state = PlayerState {
+            playing: true,
+            entry: Some(Entry {
+                index: 1,
+                name: "Intro".into(),
+                duration: 12.5,
+            }),
+            position: 3.0,
+            track_volumes: BTreeMap::from([("Track".into(), 0.5)]),
+        };
+        assert_eq!(
+            state.to_json(),
+            json!({
+                "playing": true,
+                "entry": { "index": 1, "name": "Intro", "duration": 12.5 },
+                "position": 3.0,
+                "track_volumes": { "Track": 0.5 },
+            })
+        );
+    }
+}
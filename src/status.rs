@@ -0,0 +1,168 @@
+//! Status and statistics of the MQTT connection, shown in the configuration window.
+//!
+//! The connection thread keeps them up to date while the window takes snapshots.
+
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use crate::sync;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum State {
+    /// Not configured yet or torn down.
+    #[default]
+    Disconnected,
+    /// Waiting for the broker, either initially or after the connection was lost.
+    Connecting,
+    Connected,
+}
+
+impl State {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            State::Disconnected => "Disconnected",
+            State::Connecting => "Connecting",
+            State::Connected => "Connected",
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Status {
+    pub state: State,
+    /// Address of the broker as `<server>:<port>`, empty if there never was a connection.
+    pub broker: String,
+    pub connected_since: Option<Instant>,
+    /// Times the connection was re-established after it was lost.
+    pub reconnects: u64,
+    /// Messages handed to the broker, including acknowledgements and responses.
+    pub published: u64,
+    pub received: u64,
+    pub last_error: Option<String>,
+    /// Whether the connection was established before, so that connecting again is a reconnect.
+    established: bool,
+}
+
+impl Status {
+    /// Status of a new connection to the broker, starting the statistics from zero.
+    pub fn connecting(server: &str, port: u16) -> Self {
+        // IPv6 addresses have to be bracketed to separate them from the port.
+        let broker = if server.contains(':') {
+            format!("[{server}]:{port}")
+        } else {
+            format!("{server}:{port}")
+        };
+        Status {
+            state: State::Connecting,
+            broker,
+            ..Default::default()
+        }
+    }
+
+    pub fn connected(&mut self) {
+        if self.established {
+            self.reconnects += 1;
+        }
+        self.established = true;
+        self.state = State::Connected;
+        self.connected_since = Some(Instant::now());
+    }
+
+    /// Record that the connection was lost or couldn't be established and is going to be tried
+    /// again.
+    pub fn reconnecting(&mut self) {
+        self.state = State::Connecting;
+        self.connected_since = None;
+    }
+
+    pub fn disconnected(&mut self) {
+        self.state = State::Disconnected;
+        self.connected_since = None;
+    }
+
+    pub fn failed(&mut self, error: &str) {
+        self.last_error = Some(error.into());
+    }
+
+    /// Time since the connection was established, `None` if it isn't connected.
+    pub fn connected_for(&self) -> Option<Duration> {
+        self.connected_since.map(|since| since.elapsed())
+    }
+}
+
+static STATUS: Mutex<Option<Status>> = Mutex::new(None);
+
+/// Snapshot of the current status.
+pub fn get() -> Status {
+    sync::lock(&STATUS).clone().unwrap_or_default()
+}
+
+/// Replace the status, e.g. when a new connection is set up.
+pub fn set(status: Status) {
+    *sync::lock(&STATUS) = Some(status);
+}
+
+/// Apply `update` to the current status.
+pub fn update(update: impl FnOnce(&mut Status)) {
+    update(sync::lock(&STATUS).get_or_insert_with(Status::default));
+}
+
+/// Format a duration as hours, minutes and seconds, e.g. `1:02:03`.
+pub fn format_duration(duration: Duration) -> String {
+    let seconds = duration.as_secs();
+    format!(
+        "{}:{:02}:{:02}",
+        seconds / 3600,
+        seconds / 60 % 60,
+        seconds % 60
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn transitions() {
+        let mut status = Status::connecting("broker", 1883);
+        // Failed attempts before the first connection don't count either.
+        status.reconnecting();
+        assert_eq!(status.state, State::Connecting);
+        assert_eq!(status.broker, "broker:1883");
+        assert_eq!(status.connected_for(), None);
+
+        status.connected();
+        assert_eq!(status.state, State::Connected);
+        assert!(status.connected_for().is_some());
+
+        status.failed("connection reset");
+        status.reconnecting();
+        assert_eq!(status.state, State::Connecting);
+        assert_eq!(status.connected_for(), None);
+        assert_eq!(status.last_error.as_deref(), Some("connection reset"));
+
+        // Only the connection being re-established counts, not every attempt.
+        status.reconnecting();
+        status.reconnecting();
+        assert_eq!(status.reconnects, 0);
+        status.connected();
+        assert_eq!(status.reconnects, 1);
+
+        status.disconnected();
+        assert_eq!(status.state, State::Disconnected);
+        assert_eq!(status.reconnects, 1);
+
+        assert_eq!(Status::connecting("::1", 8883).broker, "[::1]:8883");
+    }
+
+    #[test]
+    fn duration() {
+        assert_eq!(format_duration(Duration::from_secs(0)), "0:00:00");
+        assert_eq!(format_duration(Duration::from_millis(59_999)), "0:00:59");
+        assert_eq!(format_duration(Duration::from_secs(3723)), "1:02:03");
+        assert_eq!(
+            format_duration(Duration::from_secs(100 * 3600)),
+            "100:00:00"
+        );
+    }
+}
@@ -0,0 +1,61 @@
+//! Locking of the global state that survives panics of other lock holders.
+//!
+//! Panics are caught at the FFI boundary, so a lock held while panicking is poisoned but the data
+//! behind it is still used afterwards. All global state is left consistent between statements,
+//! so the poison is cleared instead of disabling the plugin until M*Player is restarted.
+
+use log::warn;
+use std::sync::{LockResult, Mutex, MutexGuard, RwLock, RwLockReadGuard, RwLockWriteGuard};
+
+fn recover<G>(result: LockResult<G>, clear_poison: impl FnOnce()) -> G {
+    result.unwrap_or_else(|e| {
+        warn!("Recovering lock poisoned by a panic");
+        clear_poison();
+        e.into_inner()
+    })
+}
+
+pub fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    recover(mutex.lock(), || mutex.clear_poison())
+}
+
+pub fn read<T>(lock: &RwLock<T>) -> RwLockReadGuard<'_, T> {
+    recover(lock.read(), || lock.clear_poison())
+}
+
+pub fn write<T>(lock: &RwLock<T>) -> RwLockWriteGuard<'_, T> {
+    recover(lock.write(), || lock.clear_poison())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    #[test]
+    fn poisoned() {
+        let mutex = Mutex::new(1);
+        let rw_lock = RwLock::new(1);
+
+        thread::scope(|s| {
+            let result = s
+                .spawn(|| {
+                    let mut value = mutex.lock().unwrap();
+                    let _guard = rw_lock.write().unwrap();
+                    *value = 2;
+                    panic!("injected panic");
+                })
+                .join();
+            assert!(result.is_err());
+        });
+        assert!(mutex.is_poisoned());
+        assert!(rw_lock.is_poisoned());
+
+        assert_eq!(*lock(&mutex), 2);
+        assert!(!mutex.is_poisoned());
+        assert_eq!(*read(&rw_lock), 1);
+        *write(&rw_lock) = 3;
+        assert!(!rw_lock.is_poisoned());
+        assert_eq!(*read(&rw_lock), 3);
+    }
+}
@@ -0,0 +1,121 @@
+//! Conversion of the C strings exchanged with M*Player.
+//!
+//! M*Player usually hands over UTF-8, but on Windows names might be encoded in the ANSI code page
+//! instead. Those are decoded as Windows-1252, which maps every byte to a distinct character, so
+//! they can be encoded again byte by byte when the name is passed back to M*Player.
+
+use core::ffi::c_char;
+use std::borrow::Cow;
+use std::ffi::{CStr, CString};
+
+/// Encoding M*Player used for a string.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Encoding {
+    #[default]
+    Utf8,
+    Windows1252,
+}
+
+/// Characters of the bytes 0x80 to 0x9F in Windows-1252.
+///
+/// The five bytes without a character keep the C1 control character of the same value.
+const WINDOWS_1252_HIGH: [char; 32] = [
+    '\u{20AC}', '\u{0081}', '\u{201A}', '\u{0192}', '\u{201E}', '\u{2026}', '\u{2020}', '\u{2021}',
+    '\u{02C6}', '\u{2030}', '\u{0160}', '\u{2039}', '\u{0152}', '\u{008D}', '\u{017D}', '\u{008F}',
+    '\u{0090}', '\u{2018}', '\u{2019}', '\u{201C}', '\u{201D}', '\u{2022}', '\u{2013}', '\u{2014}',
+    '\u{02DC}', '\u{2122}', '\u{0161}', '\u{203A}', '\u{0153}', '\u{009D}', '\u{017E}', '\u{0178}',
+];
+
+fn decode_windows_1252(bytes: &[u8]) -> String {
+    bytes
+        .iter()
+        .map(|&b| match b {
+            0x80..=0x9F => WINDOWS_1252_HIGH[usize::from(b - 0x80)],
+            _ => char::from(b),
+        })
+        .collect()
+}
+
+fn encode_windows_1252(c: char) -> Option<u8> {
+    if let Some(index) = WINDOWS_1252_HIGH.iter().position(|&high| high == c) {
+        return u8::try_from(index + 0x80).ok();
+    }
+    match u32::from(c) {
+        code @ (0..=0x7F | 0xA0..=0xFF) => u8::try_from(code).ok(),
+        _ => None,
+    }
+}
+
+/// Decode a C string, falling back to Windows-1252 if it isn't valid UTF-8.
+pub fn decode(s: &CStr) -> (Cow<'_, str>, Encoding) {
+    match s.to_str() {
+        Ok(s) => (Cow::Borrowed(s), Encoding::Utf8),
+        Err(_) => (
+            Cow::Owned(decode_windows_1252(s.to_bytes())),
+            Encoding::Windows1252,
+        ),
+    }
+}
+
+/// Decode a C string handed over by M*Player, `None` if the pointer is null.
+///
+/// # Safety
+///
+/// `s` must be null or point to a NUL-terminated string that lives for `'a`.
+pub unsafe fn from_ptr<'a>(s: *const c_char) -> Option<(Cow<'a, str>, Encoding)> {
+    if s.is_null() {
+        return None;
+    }
+    Some(decode(unsafe { CStr::from_ptr(s) }))
+}
+
+/// Encode a string to pass it to M*Player, the inverse of [`decode`].
+pub fn encode(s: &str, encoding: Encoding) -> Result<CString, String> {
+    let bytes = match encoding {
+        Encoding::Utf8 => s.as_bytes().to_vec(),
+        Encoding::Windows1252 => s
+            .chars()
+            .map(|c| encode_windows_1252(c).ok_or_else(|| format!("'{c}' not in Windows-1252")))
+            .collect::<Result<_, _>>()?,
+    };
+    CString::new(bytes).map_err(|e| e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn utf8() {
+        let (name, encoding) = decode(c"Bühne – Musik");
+        assert_eq!(name, "Bühne – Musik");
+        assert!(matches!(name, Cow::Borrowed(_)));
+        assert_eq!(encoding, Encoding::Utf8);
+        assert_eq!(
+            encode(&name, encoding).unwrap().as_c_str(),
+            c"Bühne – Musik"
+        );
+    }
+
+    #[test]
+    fn windows_1252() {
+        let raw = CString::new(b"B\xFChne \x96 Musik \x80\x81".to_vec()).unwrap();
+        let (name, encoding) = decode(&raw);
+        assert_eq!(name, "Bühne – Musik €\u{0081}");
+        assert_eq!(encoding, Encoding::Windows1252);
+        assert_eq!(encode(&name, encoding).unwrap(), raw);
+
+        // Every byte survives the round trip.
+        let all = CString::new((1..=255).collect::<Vec<u8>>()).unwrap();
+        let (name, encoding) = decode(&all);
+        assert_eq!(encode(&name, encoding).unwrap(), all);
+
+        assert!(encode("日本", Encoding::Windows1252).is_err());
+        assert!(encode("a\0b", Encoding::Utf8).is_err());
+    }
+
+    #[test]
+    fn null() {
+        assert!(unsafe { from_ptr(std::ptr::null()) }.is_none());
+    }
+}
@@ -0,0 +1,124 @@
+//! Rate limiting of position updates which M*Player reports multiple times per second.
+
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use crate::sync;
+
+/// Conditions a position update has to meet before it's published.
+pub struct Limits {
+    /// Minimum time since the last published position.
+    pub min_interval: Duration,
+    /// Minimum difference in seconds to the last published position.
+    pub min_delta: f64,
+}
+
+#[derive(Default)]
+struct PositionThrottle {
+    published: Option<(Instant, f64)>,
+    pending: Option<f64>,
+}
+
+impl PositionThrottle {
+    fn report(&mut self, position: f64, now: Instant, limits: &Limits) -> bool {
+        let due = match self.published {
+            None => true,
+            Some((published_at, published_position)) => {
+                now.duration_since(published_at) >= limits.min_interval
+                    && (position - published_position).abs() >= limits.min_delta
+            }
+        };
+
+        if due {
+            self.published = Some((now, position));
+            self.pending = None;
+        } else {
+            self.pending = Some(position);
+        }
+
+        due
+    }
+
+    fn take_pending(&mut self, now: Instant) -> Option<f64> {
+        let position = self.pending.take()?;
+        self.published = Some((now, position));
+        Some(position)
+    }
+}
+
+static POSITIONS: Mutex<BTreeMap<String, PositionThrottle>> = Mutex::new(BTreeMap::new());
+
+/// Record a position reported for the named player.
+///
+/// Returns whether it should be published now.
+pub fn report(player_name: &str, position: f64, limits: &Limits) -> bool {
+    sync::lock(&POSITIONS)
+        .entry(player_name.into())
+        .or_default()
+        .report(position, Instant::now(), limits)
+}
+
+/// Take the last position reported for the named player if it wasn't published yet.
+pub fn take_pending(player_name: &str) -> Option<f64> {
+    sync::lock(&POSITIONS)
+        .get_mut(player_name)?
+        .take_pending(Instant::now())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unlimited() {
+        let limits = Limits {
+            min_interval: Duration::ZERO,
+            min_delta: 0.0,
+        };
+        let now = Instant::now();
+        let mut throttle = PositionThrottle::default();
+
+        assert!(throttle.report(0.0, now, &limits));
+        assert!(throttle.report(0.0, now, &limits));
+        assert!(throttle.report(0.1, now, &limits));
+        assert_eq!(throttle.take_pending(now), None);
+    }
+
+    #[test]
+    fn interval() {
+        let limits = Limits {
+            min_interval: Duration::from_millis(500),
+            min_delta: 0.0,
+        };
+        let now = Instant::now();
+        let mut throttle = PositionThrottle::default();
+
+        assert!(throttle.report(0.0, now, &limits));
+        assert!(!throttle.report(0.1, now + Duration::from_millis(100), &limits));
+        assert!(!throttle.report(0.4, now + Duration::from_millis(400), &limits));
+        assert!(throttle.report(0.5, now + Duration::from_millis(500), &limits));
+        assert!(!throttle.report(0.6, now + Duration::from_millis(600), &limits));
+
+        let later = now + Duration::from_millis(700);
+        assert_eq!(throttle.take_pending(later), Some(0.6));
+        assert_eq!(throttle.take_pending(later), None);
+        assert!(!throttle.report(0.8, now + Duration::from_millis(800), &limits));
+    }
+
+    #[test]
+    fn delta() {
+        let limits = Limits {
+            min_interval: Duration::ZERO,
+            min_delta: 1.0,
+        };
+        let now = Instant::now();
+        let mut throttle = PositionThrottle::default();
+
+        assert!(throttle.report(10.0, now, &limits));
+        assert!(!throttle.report(10.5, now, &limits));
+        assert!(throttle.report(11.0, now, &limits));
+        // Jumping back is a change as well.
+        assert!(throttle.report(2.0, now, &limits));
+    }
+}
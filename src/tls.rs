@@ -0,0 +1,154 @@
+//! TLS settings for the connection to the MQTT broker.
+
+use rumqttc::tokio_rustls::rustls;
+use rustls::client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier};
+use rustls::client::WebPkiServerVerifier;
+use rustls::pki_types::{CertificateDer, PrivateKeyDer, ServerName, UnixTime};
+use rustls::{
+    CertificateError, ClientConfig, DigitallySignedStruct, RootCertStore, SignatureScheme,
+};
+use std::fs::File;
+use std::io::BufReader;
+use std::sync::Arc;
+
+use crate::Configuration;
+
+/// Verifies the certificate chain of the broker but accepts any hostname.
+#[derive(Debug)]
+struct NoHostnameVerification(Arc<WebPkiServerVerifier>);
+
+impl ServerCertVerifier for NoHostnameVerification {
+    fn verify_server_cert(
+        &self,
+        end_entity: &CertificateDer<'_>,
+        intermediates: &[CertificateDer<'_>],
+        server_name: &ServerName<'_>,
+        ocsp_response: &[u8],
+        now: UnixTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        match self
+            .0
+            .verify_server_cert(end_entity, intermediates, server_name, ocsp_response, now)
+        {
+            Err(rustls::Error::InvalidCertificate(CertificateError::NotValidForName)) => {
+                Ok(ServerCertVerified::assertion())
+            }
+            result => result,
+        }
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        self.0.verify_tls12_signature(message, cert, dss)
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        self.0.verify_tls13_signature(message, cert, dss)
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.0.supported_verify_schemes()
+    }
+}
+
+fn open(path: &str) -> Result<BufReader<File>, String> {
+    File::open(path)
+        .map(BufReader::new)
+        .map_err(|e| format!("Can't open '{path}': {e}"))
+}
+
+fn read_certificates(path: &str) -> Result<Vec<CertificateDer<'static>>, String> {
+    let certificates = rustls_pemfile::certs(&mut open(path)?)
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| format!("Can't read certificates from '{path}': {e}"))?;
+
+    if certificates.is_empty() {
+        return Err(format!("No certificate found in '{path}'"));
+    }
+
+    Ok(certificates)
+}
+
+fn read_private_key(path: &str) -> Result<PrivateKeyDer<'static>, String> {
+    rustls_pemfile::private_key(&mut open(path)?)
+        .map_err(|e| format!("Can't read private key from '{path}': {e}"))?
+        .ok_or_else(|| format!("No private key found in '{path}'"))
+}
+
+/// Certificates of the given CA file or the platform's certificates if no file is given.
+fn root_certificates(ca_file: &str) -> Result<RootCertStore, String> {
+    let mut roots = RootCertStore::empty();
+
+    if ca_file.is_empty() {
+        let certificates = rustls_native_certs::load_native_certs()
+            .map_err(|e| format!("Can't load platform certificates: {e}"))?;
+        roots.add_parsable_certificates(certificates);
+    } else {
+        for certificate in read_certificates(ca_file)? {
+            roots
+                .add(certificate)
+                .map_err(|e| format!("Invalid CA certificate in '{ca_file}': {e}"))?;
+        }
+    }
+
+    Ok(roots)
+}
+
+/// Build the TLS client configuration from the plugin configuration.
+///
+/// The error describes which file or setting was rejected.
+pub fn client_config(config: &Configuration) -> Result<ClientConfig, String> {
+    let roots = Arc::new(root_certificates(&config.tls_ca_file)?);
+
+    let builder = if config.tls_skip_hostname_verification {
+        let verifier = WebPkiServerVerifier::builder(roots)
+            .build()
+            .map_err(|e| format!("Can't verify certificates: {e}"))?;
+        ClientConfig::builder()
+            .dangerous()
+            .with_custom_certificate_verifier(Arc::new(NoHostnameVerification(verifier)))
+    } else {
+        ClientConfig::builder().with_root_certificates(roots)
+    };
+
+    if config.tls_client_certificate_file.is_empty() && config.tls_client_key_file.is_empty() {
+        return Ok(builder.with_no_client_auth());
+    }
+
+    let certificates = read_certificates(&config.tls_client_certificate_file)?;
+    let key = read_private_key(&config.tls_client_key_file)?;
+    builder
+        .with_client_auth_cert(certificates, key)
+        .map_err(|e| format!("Invalid client certificate or key: {e}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn missing_files() {
+        let config = Configuration {
+            tls_ca_file: "does-not-exist.pem".into(),
+            ..Default::default()
+        };
+        let error = client_config(&config).unwrap_err();
+        assert!(error.contains("does-not-exist.pem"), "{error}");
+
+        let config = Configuration {
+            tls_client_certificate_file: "client-does-not-exist.pem".into(),
+            ..Default::default()
+        };
+        let error = client_config(&config).unwrap_err();
+        assert!(error.contains("client-does-not-exist.pem"), "{error}");
+    }
+}
@@ -0,0 +1,531 @@
+//! Layout of the topics and escaping of names which are used as topic levels.
+//!
+//! Player and track names may contain characters with a special meaning in MQTT topics. They're
+//! percent-encoded, as is `%` itself so that escaped names can be told apart from plain ones.
+
+use std::fmt::{self, Display};
+use std::str::FromStr;
+
+/// Whether `c` has to be percent-encoded in a topic level.
+fn needs_escaping(c: char) -> bool {
+    matches!(c, '/' | '+' | '#' | '\0' | '%')
+}
+
+/// Turn a name into a single topic level.
+pub fn escape(name: &str) -> String {
+    let mut level = String::with_capacity(name.len());
+    for c in name.chars() {
+        if needs_escaping(c) {
+            level.push_str(&format!("%{:02X}", c as u32));
+        } else {
+            level.push(c);
+        }
+    }
+    level
+}
+
+/// Turn a topic level back into the name it was escaped from.
+///
+/// The error describes the invalid escape sequence and is meant for logging.
+pub fn unescape(level: &str) -> Result<String, String> {
+    let mut name = Vec::with_capacity(level.len());
+    let mut bytes = level.bytes();
+    while let Some(b) = bytes.next() {
+        if b != b'%' {
+            name.push(b);
+            continue;
+        }
+
+        let hex = [bytes.next(), bytes.next()];
+        let hex = match hex {
+            [Some(high), Some(low)] => [high, low],
+            _ => return Err(format!("incomplete escape sequence in '{level}'")),
+        };
+        let decoded = std::str::from_utf8(&hex)
+            .ok()
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+            .ok_or_else(|| format!("invalid escape sequence in '{level}'"))?;
+        name.push(decoded);
+    }
+
+    String::from_utf8(name).map_err(|e| format!("invalid ({e}) name '{level}'"))
+}
+
+/// What happened to a player, filling the `{track}` and `{event}` levels of monitor topics.
+#[derive(Clone, Copy, Debug)]
+pub struct Event<'a> {
+    /// Name of the event, e.g. `playing` or `playlist/3/name`.
+    pub name: &'a str,
+    /// Track the event concerns, if any.
+    pub track: Option<&'a str>,
+}
+
+impl Event<'_> {
+    /// Topic levels of the event if the template has no `{track}` level, e.g. `track/Drums/volume`.
+    pub fn path(&self) -> String {
+        match self.track {
+            Some(track) => format!("track/{}/{}", escape(track), self.name),
+            None => self.name.into(),
+        }
+    }
+}
+
+impl<'a> From<&'a str> for Event<'a> {
+    fn from(name: &'a str) -> Self {
+        Event { name, track: None }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Level {
+    Literal(String),
+    Prefix,
+    Player,
+    Track,
+    /// The event of monitor topics or the command of control topics.
+    Event,
+}
+
+/// Parse a topic template whose last level is the `last` placeholder.
+///
+/// The error describes the problem and is meant for the configuration dialog and logging.
+fn parse_template(template: &str, last: &str) -> Result<Vec<Level>, String> {
+    if template.is_empty() {
+        return Err("The topic template is empty.".into());
+    }
+
+    let levels = template
+        .split('/')
+        .map(|level| match level {
+            "{prefix}" => Ok(Level::Prefix),
+            "{player}" => Ok(Level::Player),
+            "{track}" => Ok(Level::Track),
+            _ if level == last => Ok(Level::Event),
+            _ if level.contains(['{', '}', '+', '#', '\0', '\n']) => {
+                Err(format!("The topic level '{level}' is invalid."))
+            }
+            _ => Ok(Level::Literal(level.into())),
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let count = |placeholder: Level| levels.iter().filter(|l| **l == placeholder).count();
+    if count(Level::Prefix) > 1 {
+        return Err("{prefix} can only be used once.".into());
+    }
+    if count(Level::Player) != 1 {
+        return Err("{player} has to be used once.".into());
+    }
+    if count(Level::Event) != 1 || levels.last() != Some(&Level::Event) {
+        return Err(format!("{last} has to be the last level."));
+    }
+    match levels.iter().position(|l| *l == Level::Track) {
+        Some(i) if count(Level::Track) > 1 || levels[i + 1] != Level::Event => {
+            return Err(format!(
+                "{{track}} can only be used once, directly before {last}."
+            ));
+        }
+        _ => {}
+    }
+
+    Ok(levels)
+}
+
+/// Layout of the topics the plugin publishes on.
+///
+/// The `{track}` level is left out for events which don't concern a track. Without a `{track}`
+/// level the track is part of the event, e.g. `track/Drums/volume`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct MonitorTemplate {
+    template: String,
+    levels: Vec<Level>,
+}
+
+impl MonitorTemplate {
+    pub fn topic(&self, prefix: &str, player_name: &str, event: &Event) -> String {
+        let has_track_level = self.levels.contains(&Level::Track);
+        let mut levels = Vec::new();
+        for level in &self.levels {
+            match level {
+                Level::Literal(literal) => levels.push(literal.clone()),
+                Level::Prefix => levels.push(prefix.into()),
+                Level::Player => levels.push(escape(player_name)),
+                Level::Track => levels.extend(event.track.map(escape)),
+                Level::Event if has_track_level => levels.push(event.name.into()),
+                Level::Event => levels.push(event.path()),
+            }
+        }
+        levels.join("/")
+    }
+}
+
+impl Default for MonitorTemplate {
+    fn default() -> Self {
+        "{prefix}/monitor/{player}/{event}"
+            .parse()
+            .expect("default template should be valid")
+    }
+}
+
+impl FromStr for MonitorTemplate {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(MonitorTemplate {
+            template: s.into(),
+            levels: parse_template(s, "{event}")?,
+        })
+    }
+}
+
+impl Display for MonitorTemplate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.template)
+    }
+}
+
+/// Control command addressed by a topic, with the names still escaped.
+#[derive(Debug, PartialEq)]
+pub struct Command<'a> {
+    pub player_name: &'a str,
+    /// Levels of the command, e.g. `["play"]` or `["track", "Drums", "volume"]`.
+    pub command: Vec<&'a str>,
+}
+
+/// Remove the levels of `expected` from the start of `levels`.
+fn strip_levels<'a, 'b>(levels: &'b [&'a str], expected: &str) -> Option<&'b [&'a str]> {
+    let mut levels = levels;
+    for expected in expected.split('/') {
+        let (first, others) = levels.split_first()?;
+        if *first != expected {
+            return None;
+        }
+        levels = others;
+    }
+    Some(levels)
+}
+
+/// Layout of the topics the plugin receives commands on.
+///
+/// With a `{track}` level, track commands put the track there and the command is a single level.
+/// Commands which don't concern a track leave the `{track}` level out.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ControlTemplate {
+    template: String,
+    levels: Vec<Level>,
+}
+
+impl ControlTemplate {
+    /// Topic filter matching all control topics.
+    pub fn filter(&self, prefix: &str) -> String {
+        let mut levels = Vec::new();
+        for level in &self.levels {
+            match level {
+                Level::Literal(literal) => levels.push(literal.as_str()),
+                Level::Prefix => levels.push(prefix),
+                Level::Player => levels.push("+"),
+                Level::Track | Level::Event => {
+                    levels.push("#");
+                    break;
+                }
+            }
+        }
+        levels.join("/")
+    }
+
+    /// Find player and command in a received topic, `None` if it doesn't match the template.
+    pub fn parse<'a>(&self, prefix: &str, topic: &'a str) -> Option<Command<'a>> {
+        let topic_levels: Vec<&str> = topic.split('/').collect();
+        let mut rest = topic_levels.as_slice();
+        let mut player_name = None;
+        let mut track = None;
+
+        for level in &self.levels {
+            match level {
+                Level::Literal(literal) => {
+                    rest = strip_levels(rest, literal)?;
+                }
+                Level::Prefix => {
+                    rest = strip_levels(rest, prefix)?;
+                }
+                Level::Player => {
+                    let (first, others) = rest.split_first()?;
+                    player_name = Some(*first);
+                    rest = others;
+                }
+                Level::Track => {
+                    if let [first, command] = rest {
+                        track = Some(*first);
+                        rest = std::slice::from_ref(command);
+                    } else if rest.len() != 1 {
+                        return None;
+                    }
+                }
+                Level::Event => {
+                    if rest.is_empty() {
+                        return None;
+                    }
+                }
+            }
+        }
+
+        let command = match (track, rest) {
+            (Some(track), [command]) => vec!["track", track, command],
+            (_, rest) => rest.to_vec(),
+        };
+        Some(Command {
+            player_name: player_name?,
+            command,
+        })
+    }
+}
+
+impl Default for ControlTemplate {
+    fn default() -> Self {
+        "{prefix}/control/{player}/{command}"
+            .parse()
+            .expect("default template should be valid")
+    }
+}
+
+impl FromStr for ControlTemplate {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(ControlTemplate {
+            template: s.into(),
+            levels: parse_template(s, "{command}")?,
+        })
+    }
+}
+
+impl Display for ControlTemplate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.template)
+    }
+}
+
+/// Level of monitor topics as far as it's known from the template.
+#[derive(Clone, Copy)]
+enum Pattern<'a> {
+    Fixed(&'a str),
+    /// A single level, e.g. the player name.
+    Any,
+    /// One or more levels, i.e. the event.
+    Many,
+}
+
+/// Whether a topic following `patterns` could match `filter`.
+fn could_match(patterns: &[Pattern], filter: &[&str]) -> bool {
+    match (patterns.split_first(), filter.split_first()) {
+        (_, Some((&"#", _))) => true,
+        (None, None) => true,
+        (Some((Pattern::Fixed(level), patterns)), Some((filter_level, filter))) => {
+            (*filter_level == "+" || filter_level == level) && could_match(patterns, filter)
+        }
+        (Some((Pattern::Any, patterns)), Some((_, filter))) => could_match(patterns, filter),
+        (Some((Pattern::Many, others)), Some((_, filter))) => {
+            could_match(others, filter) || could_match(patterns, filter)
+        }
+        _ => false,
+    }
+}
+
+/// Check that the plugin doesn't receive its own monitor topics as commands, which would make it
+/// acknowledge its acknowledgements forever.
+///
+/// The error is meant for the configuration dialog and logging.
+pub fn check_templates(
+    prefix: &str,
+    monitor: &MonitorTemplate,
+    control: &ControlTemplate,
+) -> Result<(), String> {
+    let filter = control.filter(prefix);
+    let filter: Vec<&str> = filter.split('/').collect();
+
+    // Topics of events concerning a track have an additional level if there's a {track} level.
+    for with_track in [false, true] {
+        let mut patterns = Vec::new();
+        for level in &monitor.levels {
+            match level {
+                Level::Literal(literal) => patterns.push(Pattern::Fixed(literal)),
+                Level::Prefix => patterns.extend(prefix.split('/').map(Pattern::Fixed)),
+                Level::Player => patterns.push(Pattern::Any),
+                Level::Track if with_track => patterns.push(Pattern::Any),
+                Level::Track => {}
+                Level::Event => patterns.push(Pattern::Many),
+            }
+        }
+        if could_match(&patterns, &filter) {
+            return Err(format!(
+                "Monitor topics would match the control topics '{}'.",
+                filter.join("/")
+            ));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn escaping() {
+        assert_eq!(escape("Main"), "Main");
+        assert_eq!(escape("Stage/Left"), "Stage%2FLeft");
+        assert_eq!(escape("FX #1"), "FX %231");
+        assert_eq!(escape("A+B"), "A%2BB");
+        assert_eq!(escape("100%"), "100%25");
+        assert_eq!(escape("nul\0"), "nul%00");
+    }
+
+    #[test]
+    fn round_trip() {
+        for name in [
+            "",
+            "Main",
+            "Stage/Left",
+            "FX #1",
+            "+/#\0%",
+            "%2F",
+            "Bühne ✓",
+        ] {
+            assert_eq!(unescape(&escape(name)).as_deref(), Ok(name));
+        }
+    }
+
+    #[test]
+    fn invalid() {
+        assert!(unescape("%").is_err());
+        assert!(unescape("%2").is_err());
+        assert!(unescape("%zz").is_err());
+        assert!(unescape("%FF").is_err());
+        // Lower case hex digits are accepted as well.
+        assert_eq!(unescape("a%2fb").as_deref(), Ok("a/b"));
+    }
+
+    #[test]
+    fn template_validation() {
+        assert!("site/{player}/{event}".parse::<MonitorTemplate>().is_ok());
+        assert!("".parse::<MonitorTemplate>().is_err());
+        assert!("{prefix}/{event}".parse::<MonitorTemplate>().is_err());
+        assert!("{prefix}/{player}/{player}/{event}"
+            .parse::<MonitorTemplate>()
+            .is_err());
+        assert!("{prefix}/{event}/{player}"
+            .parse::<MonitorTemplate>()
+            .is_err());
+        assert!("{prefix}/{player}/{command}"
+            .parse::<MonitorTemplate>()
+            .is_err());
+        assert!("{prefix}/{player}/{track}/x/{event}"
+            .parse::<MonitorTemplate>()
+            .is_err());
+        assert!("{prefix}/#/{player}/{event}"
+            .parse::<MonitorTemplate>()
+            .is_err());
+        assert!("{prefix}/{player}/{command}"
+            .parse::<ControlTemplate>()
+            .is_ok());
+        assert!("{prefix}/{player}/{event}"
+            .parse::<ControlTemplate>()
+            .is_err());
+    }
+
+    #[test]
+    fn monitor_topics() {
+        let template = MonitorTemplate::default();
+        let volume = Event {
+            name: "volume",
+            track: Some("Drums/1"),
+        };
+        assert_eq!(
+            template.topic("MStarPlayer", "Main", &"playing".into()),
+            "MStarPlayer/monitor/Main/playing"
+        );
+        assert_eq!(
+            template.topic("a/b", "FX #1", &volume),
+            "a/b/monitor/FX %231/track/Drums%2F1/volume"
+        );
+
+        let template: MonitorTemplate = "site/area/{player}/{track}/{event}".parse().unwrap();
+        assert_eq!(
+            template.topic("MStarPlayer", "Main", &"playlist/3/name".into()),
+            "site/area/Main/playlist/3/name"
+        );
+        assert_eq!(
+            template.topic("MStarPlayer", "Main", &volume),
+            "site/area/Main/Drums%2F1/volume"
+        );
+    }
+
+    #[test]
+    fn control_topics() {
+        let template = ControlTemplate::default();
+        assert_eq!(template.filter("a/b"), "a/b/control/+/#");
+        assert_eq!(
+            template.parse("a/b", "a/b/control/Main/track/Drums/volume"),
+            Some(Command {
+                player_name: "Main",
+                command: vec!["track", "Drums", "volume"],
+            })
+        );
+        assert_eq!(template.parse("a/b", "a/b/control/Main"), None);
+        assert_eq!(template.parse("a/b", "a/c/control/Main/play"), None);
+
+        let template: ControlTemplate = "site/{player}/cmd/{track}/{command}".parse().unwrap();
+        assert_eq!(template.filter("MStarPlayer"), "site/+/cmd/#");
+        assert_eq!(
+            template.parse("MStarPlayer", "site/Main/cmd/play"),
+            Some(Command {
+                player_name: "Main",
+                command: vec!["play"],
+            })
+        );
+        assert_eq!(
+            template.parse("MStarPlayer", "site/Main/cmd/Drums/volume"),
+            Some(Command {
+                player_name: "Main",
+                command: vec!["track", "Drums", "volume"],
+            })
+        );
+        assert_eq!(template.parse("MStarPlayer", "site/Main/cmd/a/b/c"), None);
+    }
+
+    #[test]
+    fn overlapping_templates() {
+        let check = |monitor: &str, control: &str| {
+            check_templates(
+                "MStarPlayer",
+                &monitor.parse().unwrap(),
+                &control.parse().unwrap(),
+            )
+        };
+        assert!(check_templates(
+            "MStarPlayer",
+            &MonitorTemplate::default(),
+            &ControlTemplate::default()
+        )
+        .is_ok());
+        assert!(check("site/{player}/state/{event}", "site/{player}/set/{command}").is_ok());
+        assert!(check("{prefix}/{player}/{event}", "site/{player}/{command}").is_ok());
+        assert!(check("{prefix}/{player}/{event}", "control/{player}/{command}").is_ok());
+
+        let error = check("{prefix}/{player}/{event}", "{prefix}/{player}/{command}").unwrap_err();
+        assert!(error.contains("'MStarPlayer/+/#'"), "{error}");
+        // The prefix is a player name to the monitor topics.
+        assert!(check("{player}/{event}", "{prefix}/{player}/{command}").is_err());
+        // Events or tracks can be called like the fixed level of the control topics.
+        assert!(check(
+            "{prefix}/{player}/{event}",
+            "{prefix}/{player}/set/{command}"
+        )
+        .is_err());
+        assert!(check(
+            "{prefix}/{player}/{track}/{event}",
+            "{prefix}/{player}/x/y/{command}"
+        )
+        .is_err());
+        assert!(check("site/{player}/{event}", "site/{player}/x/{track}/{command}").is_err());
+    }
+}
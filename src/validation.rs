@@ -0,0 +1,313 @@
+//! Checks of the connection settings, shared by the configuration dialog and loading projects.
+//!
+//! The errors are sentences meant for the configuration dialog. Names are checked for what every
+//! broker accepts, problems with them are only warnings since particular brokers accept more.
+
+use std::fmt::{self, Display};
+use std::net::IpAddr;
+
+use crate::{topic, Configuration};
+
+/// Longest client name every broker has to accept according to MQTT 3.1.1.
+pub const MAX_CLIENT_NAME_LENGTH: usize = 23;
+
+/// Check that the server is an IP address or a valid hostname.
+pub fn check_server(server: &str) -> Result<(), String> {
+    if server.is_empty() {
+        return Err("The server is missing.".into());
+    }
+    if server.parse::<IpAddr>().is_ok() {
+        return Ok(());
+    }
+
+    // A trailing dot marks a fully qualified name.
+    let hostname = server.strip_suffix('.').unwrap_or(server);
+    if hostname.len() > 253 {
+        return Err("The hostname is longer than 253 characters.".into());
+    }
+    let invalid = |reason: &str| {
+        Err(format!(
+            "'{server}' is neither an IP address nor a hostname, {reason}."
+        ))
+    };
+    for label in hostname.split('.') {
+        if label.is_empty() || label.len() > 63 {
+            return invalid("its parts have to be 1 to 63 characters long");
+        }
+        // '_' isn't allowed in hostnames but common in names resolved locally.
+        if !label
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+        {
+            return invalid("which can only contain letters, digits, '-', '_' and '.'");
+        }
+        if label.starts_with('-') || label.ends_with('-') {
+            return invalid("its parts can't start or end with '-'");
+        }
+    }
+    Ok(())
+}
+
+pub fn check_port(port: u16) -> Result<(), String> {
+    match port {
+        0 => Err("The port has to be between 1 and 65535.".into()),
+        _ => Ok(()),
+    }
+}
+
+/// Check that the client name is accepted by every broker.
+///
+/// Brokers only have to accept up to 23 letters and digits. `-` and `_` are allowed as well since
+/// all common brokers accept them.
+pub fn check_client_name(client_name: &str) -> Result<(), String> {
+    if client_name.is_empty() {
+        return Err("The client name is missing.".into());
+    }
+    if client_name.chars().count() > MAX_CLIENT_NAME_LENGTH {
+        return Err(format!(
+            "The client name is longer than {MAX_CLIENT_NAME_LENGTH} characters."
+        ));
+    }
+    if let Some(c) = client_name
+        .chars()
+        .find(|&c| !(c.is_ascii_alphanumeric() || c == '-' || c == '_'))
+    {
+        return Err(format!(
+            "The client name contains '{c}', only letters, digits, '-' and '_' are allowed."
+        ));
+    }
+    Ok(())
+}
+
+pub fn check_topic_prefix(topic_prefix: &str) -> Result<(), String> {
+    if topic_prefix.is_empty() {
+        return Err("The topic prefix is missing.".into());
+    }
+    if let Some(c) = topic_prefix.chars().find(|c| matches!(c, '+' | '#' | '\0')) {
+        return Err(format!(
+            "The topic prefix can't contain '{}'.",
+            c.escape_default()
+        ));
+    }
+    if topic_prefix.starts_with('/') || topic_prefix.ends_with('/') {
+        return Err("The topic prefix can't start or end with '/'.".into());
+    }
+    if topic_prefix.split('/').any(str::is_empty) {
+        return Err("The topic prefix can't contain empty levels.".into());
+    }
+    Ok(())
+}
+
+pub fn check_reconnect_delays(delay: u32, max_delay: u32) -> Result<(), String> {
+    if delay == 0 {
+        return Err("The first delay before reconnecting has to be at least 1 ms.".into());
+    }
+    if max_delay < delay {
+        return Err(
+            "The maximum delay before reconnecting can't be shorter than the first.".into(),
+        );
+    }
+    Ok(())
+}
+
+pub fn check_reconnect_jitter(jitter: u32) -> Result<(), String> {
+    if jitter > 100 {
+        return Err("The jitter has to be between 0 and 100 %.".into());
+    }
+    Ok(())
+}
+
+/// How a problem is treated in the configuration dialog and when loading a project.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Severity {
+    /// The broker might accept the setting anyway, so it can be used.
+    Warning,
+    /// The setting can't work, so the configuration can't be applied.
+    Error,
+}
+
+/// Problem with a setting of the configuration.
+#[derive(Debug, PartialEq, Eq)]
+pub struct FieldError {
+    pub field: &'static str,
+    pub message: String,
+    pub severity: Severity,
+}
+
+impl Display for FieldError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Invalid {} in configuration: {}",
+            self.field, self.message
+        )
+    }
+}
+
+/// How a problem with the server or client name is treated.
+///
+/// Only missing names can't work, brokers differ in which names they accept.
+pub fn name_severity(name: &str) -> Severity {
+    if name.is_empty() {
+        Severity::Error
+    } else {
+        Severity::Warning
+    }
+}
+
+/// Check all connection settings, returning every problem found.
+pub fn validate(config: &Configuration) -> Result<(), Vec<FieldError>> {
+    let errors: Vec<FieldError> = [
+        (
+            "server",
+            name_severity(&config.server),
+            check_server(&config.server),
+        ),
+        ("port", Severity::Error, check_port(config.port)),
+        (
+            "client name",
+            name_severity(&config.client_name),
+            check_client_name(&config.client_name),
+        ),
+        (
+            "topic prefix",
+            Severity::Error,
+            check_topic_prefix(&config.topic_prefix),
+        ),
+        (
+            "topic templates",
+            Severity::Error,
+            topic::check_templates(
+                &config.topic_prefix,
+                &config.monitor_topic_template,
+                &config.control_topic_template,
+            ),
+        ),
+        (
+            "reconnect delay",
+            Severity::Error,
+            check_reconnect_delays(config.reconnect_delay, config.reconnect_max_delay),
+        ),
+        (
+            "reconnect jitter",
+            Severity::Error,
+            check_reconnect_jitter(config.reconnect_jitter),
+        ),
+    ]
+    .into_iter()
+    .filter_map(|(field, severity, result)| {
+        result.err().map(|message| FieldError {
+            field,
+            message,
+            severity,
+        })
+    })
+    .collect();
+
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(errors)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn server() {
+        assert!(check_server("127.0.0.1").is_ok());
+        assert!(check_server("::1").is_ok());
+        assert!(check_server("broker").is_ok());
+        assert!(check_server("mqtt-1.example.com.").is_ok());
+        assert!(check_server("mqtt_broker.local").is_ok());
+
+        assert!(check_server("").is_err());
+        assert!(check_server("broker..local").is_err());
+        assert!(check_server("-broker").is_err());
+        assert!(check_server("broker local").is_err());
+        assert!(check_server("broker:1883").is_err());
+        assert!(check_server(&"a".repeat(64)).is_err());
+        assert!(check_server(&["a"; 128].join(".")).is_err());
+    }
+
+    #[test]
+    fn client_name() {
+        assert!(check_client_name("MStarPlayer").is_ok());
+        assert!(check_client_name("MStarPlayer-stage_1").is_ok());
+
+        assert!(check_client_name("").is_err());
+        assert!(check_client_name("MStarPlayer-on-the-main-stage").is_err());
+        let error = check_client_name("M*Player").unwrap_err();
+        assert!(error.contains('*'), "{error}");
+    }
+
+    #[test]
+    fn topic_prefix() {
+        assert!(check_topic_prefix("MStarPlayer").is_ok());
+        assert!(check_topic_prefix("site/stage").is_ok());
+
+        assert!(check_topic_prefix("").is_err());
+        assert!(check_topic_prefix("site/+").is_err());
+        assert!(check_topic_prefix("site/#").is_err());
+        assert!(check_topic_prefix("/site").is_err());
+        assert!(check_topic_prefix("site/").is_err());
+        assert!(check_topic_prefix("site//stage").is_err());
+    }
+
+    #[test]
+    fn reconnect() {
+        assert!(check_reconnect_delays(1000, 60000).is_ok());
+        assert!(check_reconnect_delays(1000, 1000).is_ok());
+        assert!(check_reconnect_delays(0, 60000).is_err());
+        assert!(check_reconnect_delays(1000, 999).is_err());
+
+        assert!(check_reconnect_jitter(100).is_ok());
+        assert!(check_reconnect_jitter(101).is_err());
+    }
+
+    #[test]
+    fn configuration() {
+        assert_eq!(validate(&Configuration::default()), Ok(()));
+
+        let config = Configuration {
+            server: "broker local".into(),
+            port: 0,
+            ..Default::default()
+        };
+        let errors = validate(&config).unwrap_err();
+        let fields: Vec<_> = errors.iter().map(|e| (e.field, e.severity)).collect();
+        assert_eq!(
+            fields,
+            [("server", Severity::Warning), ("port", Severity::Error)]
+        );
+        assert!(errors[0]
+            .to_string()
+            .starts_with("Invalid server in configuration: "));
+
+        let config = Configuration {
+            server: String::new(),
+            client_name: "MStarPlayer on the main stage".into(),
+            ..Default::default()
+        };
+        let errors = validate(&config).unwrap_err();
+        let fields: Vec<_> = errors.iter().map(|e| (e.field, e.severity)).collect();
+        assert_eq!(
+            fields,
+            [
+                ("server", Severity::Error),
+                ("client name", Severity::Warning)
+            ]
+        );
+
+        let config = Configuration {
+            monitor_topic_template: "{prefix}/{player}/{event}".parse().unwrap(),
+            control_topic_template: "{prefix}/{player}/{command}".parse().unwrap(),
+            ..Default::default()
+        };
+        let errors = validate(&config).unwrap_err();
+        let fields: Vec<_> = errors.iter().map(|e| (e.field, e.severity)).collect();
+        assert_eq!(fields, [("topic templates", Severity::Error)]);
+    }
+}
@@ -19,11 +19,48 @@ static SERVER: &str = "127.0.0.1";
 static PORT: u16 = 1883;
 static TOPIC_PREFIX: &str = "integration-test";
 
+fn is_state(p: &Publish) -> bool {
+    p.topic.ends_with("/state")
+}
+
+/// Whether the message reports how the plugin connected, which races with the first events.
+fn is_connection(p: &Publish) -> bool {
+    p.topic == format!("{TOPIC_PREFIX}/monitor/_plugin/connection")
+}
+
+/// Wait for the next published event, skipping state updates and connection reports.
+///
+/// Retained messages from previous runs are delivered on subscribe and are skipped as well.
 fn wait_for_publish(connection: &mut Connection) -> Option<Publish> {
+    wait_for_publish_matching(connection, |p| {
+        !p.retain && !is_state(p) && !is_connection(p)
+    })
+}
+
+/// Wait for the next published state update.
+fn wait_for_state(connection: &mut Connection) -> Option<Publish> {
+    wait_for_publish_matching(connection, |p| !p.retain && is_state(p))
+}
+
+/// Wait until the plugin announced that it's online, either retained or live.
+fn wait_for_online(connection: &mut Connection) {
+    let topic = format!("{TOPIC_PREFIX}/monitor/_plugin/availability");
+    wait_for_publish_matching(connection, |p| {
+        p.topic == topic && p.payload.as_ref() == b"online"
+    })
+    .expect("plugin didn't announce that it's online");
+}
+
+fn wait_for_publish_matching(
+    connection: &mut Connection,
+    predicate: impl Fn(&Publish) -> bool,
+) -> Option<Publish> {
     loop {
         match connection.recv_timeout(TIMEOUT) {
             Ok(Ok(rumqttc::Event::Incoming(rumqttc::Incoming::Publish(p)))) => {
-                return Some(p);
+                if predicate(&p) {
+                    return Some(p);
+                }
             }
             Ok(Ok(_)) => {}
             Ok(Err(e)) => {
@@ -38,17 +75,18 @@ fn wait_for_publish(connection: &mut Connection) -> Option<Publish> {
     }
 }
 
-fn wait_for_no_publish(connection: &mut Connection) {
+fn wait_for_suback(connection: &mut Connection) {
     loop {
         match connection.recv_timeout(TIMEOUT) {
-            Ok(Ok(rumqttc::Event::Incoming(rumqttc::Incoming::Publish(_)))) => {
-                panic!("Should not have received a message.");
+            Ok(Ok(rumqttc::Event::Incoming(rumqttc::Incoming::SubAck(_)))) => {
+                break;
             }
             Ok(Ok(_)) => {}
             Ok(Err(e)) => {
                 error!("{}", e);
             }
             Err(_) => {
+                error!("Timeout waiting for subscription");
                 break;
             }
         }
@@ -59,6 +97,10 @@ fn publish_and_wait(client: &mut Client, topic: String, connection: &mut Connect
     client
         .publish(topic, QoS::AtLeastOnce, false, Vec::new())
         .unwrap();
+    wait_for_puback(connection);
+}
+
+fn wait_for_puback(connection: &mut Connection) {
     loop {
         match connection.recv_timeout(TIMEOUT) {
             Ok(Ok(rumqttc::Event::Incoming(rumqttc::Incoming::PubAck(_)))) => {
@@ -75,13 +117,23 @@ fn publish_and_wait(client: &mut Client, topic: String, connection: &mut Connect
     }
 
     // Wait some extra time for the received message to be handled.
-    std::thread::sleep(Duration::from_millis(10));
+    std::thread::sleep(Duration::from_millis(100));
+}
+
+/// Wait for the acknowledgement of a control command of the named player.
+fn check_ack(connection: &mut Connection, player_name: &str, expected_payload: &str) {
+    let p = wait_for_publish(connection).expect("no acknowledgement published");
+    assert_eq!(p.topic, format!("{TOPIC_PREFIX}/monitor/{player_name}/ack"));
+    assert_eq!(
+        String::from_utf8(p.payload.to_vec()).unwrap(),
+        expected_payload
+    );
 }
 
 fn new_player_name_predicate(player_name: &CString) -> impl Fn(&*const c_char) -> bool {
     let player_name = player_name.clone();
     move |p: &*const c_char| {
-        let p = unsafe { CStr::from_ptr(p.clone()) };
+        let p = unsafe { CStr::from_ptr(*p) };
 
         p.to_bytes() == player_name.as_bytes()
     }
@@ -107,6 +159,17 @@ fn mqtt_interaction() {
         mstarLoadConfiguration(input_configuration_raw.as_ptr());
     }
 
+    // Commands are only executed for players known to M*Player.
+    let list_players_ctx = MockCallbacks::list_players_context();
+    list_players_ctx
+        .expect()
+        .returning(|_, callback, user_data| {
+            callback(c"Test Player".as_ptr(), user_data);
+            callback(c"Other Player".as_ptr(), user_data);
+            // M*Player on Windows might use the ANSI code page.
+            callback(c"B\xFChne".as_ptr(), user_data);
+        });
+
     let options = MqttOptions::new("MStarPlayer-MQTT-test", SERVER, PORT);
 
     let (mut client, mut connection) = Client::new(options, 10);
@@ -114,6 +177,8 @@ fn mqtt_interaction() {
     client
         .subscribe(format!("{}/monitor/#", TOPIC_PREFIX), QoS::AtMostOnce)
         .unwrap();
+    wait_for_suback(&mut connection);
+    wait_for_online(&mut connection);
 
     let player_name = CString::new("Test Player").unwrap();
     mstarPlayingStateChanged(player_name.as_ptr(), true);
@@ -126,6 +191,19 @@ fn mqtt_interaction() {
         assert!(p.payload.is_empty());
     }
 
+    let p = wait_for_state(&mut connection).expect("no state update published");
+    assert_eq!(p.topic, format!("{TOPIC_PREFIX}/monitor/Test Player/state"));
+    let state: serde_json::Value = serde_json::from_slice(&p.payload).unwrap();
+    assert_eq!(
+        state,
+        serde_json::json!({
+            "playing": true,
+            "entry": null,
+            "position": 0.0,
+            "track_volumes": {},
+        })
+    );
+
     mstarNextEntrySelected(player_name.as_ptr());
 
     if let Some(p) = wait_for_publish(&mut connection) {
@@ -143,21 +221,60 @@ fn mqtt_interaction() {
         assert!(p.payload.is_empty());
     }
 
-    mstarPlaylistEntrySelected(player_name.as_ptr(), 0, player_name.as_ptr(), 0.0);
-
-    wait_for_no_publish(&mut connection);
-
-    mstarPlaylistEntryDurationChanged(player_name.as_ptr(), 0, 0.0);
-
-    wait_for_no_publish(&mut connection);
-
-    mstarPlaylistEntryNameChanged(player_name.as_ptr(), 0, player_name.as_ptr());
-
-    wait_for_no_publish(&mut connection);
-
-    mstarTrackVolumeChanged(player_name.as_ptr(), player_name.as_ptr(), 0.0);
-
-    wait_for_no_publish(&mut connection);
+    // Characters with a special meaning in topics are escaped.
+    let special_player_name = CString::new("Stage/Left #1").unwrap();
+    mstarNextEntrySelected(special_player_name.as_ptr());
+
+    let p = wait_for_publish(&mut connection).expect("no message published");
+    assert_eq!(
+        p.topic,
+        format!("{TOPIC_PREFIX}/monitor/Stage%2FLeft %231/next")
+    );
+
+    // Names which aren't UTF-8 are decoded as Windows-1252.
+    let ansi_player_name = CString::new(b"B\xFChne".to_vec()).unwrap();
+    mstarNextEntrySelected(ansi_player_name.as_ptr());
+
+    let p = wait_for_publish(&mut connection).expect("no message published");
+    assert_eq!(p.topic, format!("{TOPIC_PREFIX}/monitor/Bühne/next"));
+
+    let entry_name = CString::new("Test Entry").unwrap();
+    mstarPlaylistEntrySelected(player_name.as_ptr(), 2, entry_name.as_ptr(), 12.5);
+
+    let p = wait_for_publish(&mut connection).expect("no message published");
+    assert_eq!(p.topic, format!("{TOPIC_PREFIX}/monitor/Test Player/entry"));
+    assert_eq!(
+        String::from_utf8(p.payload.to_vec()).unwrap(),
+        "2\nTest Entry\n12.5"
+    );
+
+    mstarPlaylistEntryDurationChanged(player_name.as_ptr(), 3, 42.0);
+
+    let p = wait_for_publish(&mut connection).expect("no message published");
+    assert_eq!(
+        p.topic,
+        format!("{TOPIC_PREFIX}/monitor/Test Player/playlist/3/duration")
+    );
+    assert_eq!(String::from_utf8(p.payload.to_vec()).unwrap(), "42");
+
+    mstarPlaylistEntryNameChanged(player_name.as_ptr(), 3, entry_name.as_ptr());
+
+    let p = wait_for_publish(&mut connection).expect("no message published");
+    assert_eq!(
+        p.topic,
+        format!("{TOPIC_PREFIX}/monitor/Test Player/playlist/3/name")
+    );
+    assert_eq!(String::from_utf8(p.payload.to_vec()).unwrap(), "Test Entry");
+
+    let track_name = CString::new("Test Track").unwrap();
+    mstarTrackVolumeChanged(player_name.as_ptr(), track_name.as_ptr(), 0.5);
+
+    let p = wait_for_publish(&mut connection).expect("no message published");
+    assert_eq!(
+        p.topic,
+        format!("{TOPIC_PREFIX}/monitor/Test Player/track/Test Track/volume")
+    );
+    assert_eq!(String::from_utf8(p.payload.to_vec()).unwrap(), "0.5");
 
     mstarPositionChanged(player_name.as_ptr(), 0.0);
 
@@ -182,6 +299,24 @@ fn mqtt_interaction() {
             format!("{TOPIC_PREFIX}/control/Test Player/play"),
             &mut connection,
         );
+        check_ack(&mut connection, "Test Player", "play\nexecuted");
+    }
+
+    {
+        // M*Player gets back the name as it reported it.
+        let player_name = ansi_player_name.clone();
+        let ctx = MockCallbacks::play_context();
+        ctx.expect()
+            .once()
+            .return_const(())
+            .withf(new_player_name_predicate(&player_name));
+
+        publish_and_wait(
+            &mut client,
+            format!("{TOPIC_PREFIX}/control/Bühne/play"),
+            &mut connection,
+        );
+        check_ack(&mut connection, "Bühne", "play\nexecuted");
     }
 
     {
@@ -197,6 +332,7 @@ fn mqtt_interaction() {
             format!("{TOPIC_PREFIX}/control/Test Player/stop"),
             &mut connection,
         );
+        check_ack(&mut connection, "Test Player", "stop\nexecuted");
     }
 
     {
@@ -212,6 +348,7 @@ fn mqtt_interaction() {
             format!("{TOPIC_PREFIX}/control/Test Player/next"),
             &mut connection,
         );
+        check_ack(&mut connection, "Test Player", "next\nexecuted");
     }
 
     {
@@ -227,7 +364,158 @@ fn mqtt_interaction() {
             format!("{TOPIC_PREFIX}/control/Test Player/previous"),
             &mut connection,
         );
+        check_ack(&mut connection, "Test Player", "previous\nexecuted");
     }
 
+    {
+        let player_name = player_name.clone();
+        let track_name = track_name.clone();
+        let ctx = MockCallbacks::set_track_volume_context();
+        ctx.expect()
+            .once()
+            .return_const(())
+            .withf(move |p, t, volume| {
+                new_player_name_predicate(&player_name)(p)
+                    && new_player_name_predicate(&track_name)(t)
+                    && *volume == 0.25
+            });
+
+        client
+            .publish(
+                format!("{TOPIC_PREFIX}/control/Test Player/track/Test Track/volume"),
+                QoS::AtLeastOnce,
+                false,
+                r#"{"volume":0.25,"correlation_id":"volume-1"}"#,
+            )
+            .unwrap();
+        wait_for_puback(&mut connection);
+        check_ack(
+            &mut connection,
+            "Test Player",
+            "track/Test Track/volume\nexecuted\nvolume-1",
+        );
+    }
+
+    client
+        .publish(
+            format!("{TOPIC_PREFIX}/control/Test Player/track/Test Track/volume"),
+            QoS::AtLeastOnce,
+            false,
+            "loud",
+        )
+        .unwrap();
+    wait_for_puback(&mut connection);
+    check_ack(
+        &mut connection,
+        "Test Player",
+        "track/Test Track/volume\nmalformed",
+    );
+
+    publish_and_wait(
+        &mut client,
+        format!("{TOPIC_PREFIX}/control/Test Player/dance"),
+        &mut connection,
+    );
+    check_ack(&mut connection, "Test Player", "dance\nunknown-command");
+
+    // Player names which can't be unescaped are acknowledged as they were received.
+    publish_and_wait(
+        &mut client,
+        format!("{TOPIC_PREFIX}/control/Test%zzPlayer/play"),
+        &mut connection,
+    );
+    check_ack(&mut connection, "Test%25zzPlayer", "play\nmalformed");
+
+    publish_and_wait(
+        &mut client,
+        format!("{TOPIC_PREFIX}/control/Unknown Player/play"),
+        &mut connection,
+    );
+    check_ack(&mut connection, "Unknown Player", "play\nunknown-player");
+
+    // Player names are case-sensitive by default.
+    publish_and_wait(
+        &mut client,
+        format!("{TOPIC_PREFIX}/control/test player/play"),
+        &mut connection,
+    );
+    check_ack(&mut connection, "test player", "play\nunknown-player");
+
+    {
+        let player_name = player_name.clone();
+        let ctx = MockCallbacks::list_tracks_context();
+        ctx.expect()
+            .once()
+            .withf(move |p, _, _| new_player_name_predicate(&player_name)(p))
+            .returning(|_, callback, user_data| {
+                callback(c"Test Track".as_ptr(), c"1".as_ptr(), user_data);
+                callback(c"Other Track".as_ptr(), c"2".as_ptr(), user_data);
+                callback(c"Ger\xE4usche".as_ptr(), c"3".as_ptr(), user_data);
+            });
+
+        publish_and_wait(
+            &mut client,
+            format!("{TOPIC_PREFIX}/control/Test Player/list-tracks"),
+            &mut connection,
+        );
+
+        let p = wait_for_publish(&mut connection).expect("no message published");
+        assert_eq!(
+            p.topic,
+            format!("{TOPIC_PREFIX}/monitor/Test Player/tracks")
+        );
+        assert_eq!(
+            String::from_utf8(p.payload.to_vec()).unwrap(),
+            "Test Track\t1\nOther Track\t2\nGeräusche\t3"
+        );
+        check_ack(&mut connection, "Test Player", "list-tracks\nexecuted");
+    }
+
+    {
+        // Track names are passed back as listed even if the player name is plain ASCII.
+        let player_name = player_name.clone();
+        let track_name = CString::new(b"Ger\xE4usche".to_vec()).unwrap();
+        let ctx = MockCallbacks::set_track_volume_context();
+        ctx.expect().once().return_const(()).withf(move |p, t, _| {
+            new_player_name_predicate(&player_name)(p) && new_player_name_predicate(&track_name)(t)
+        });
+
+        client
+            .publish(
+                format!("{TOPIC_PREFIX}/control/Test Player/track/Geräusche/volume"),
+                QoS::AtLeastOnce,
+                false,
+                "0.75",
+            )
+            .unwrap();
+        wait_for_puback(&mut connection);
+        check_ack(
+            &mut connection,
+            "Test Player",
+            "track/Geräusche/volume\nexecuted",
+        );
+    }
+
+    publish_and_wait(
+        &mut client,
+        format!("{TOPIC_PREFIX}/control/_plugin/list-players"),
+        &mut connection,
+    );
+
+    let p = wait_for_publish(&mut connection).expect("no message published");
+    assert_eq!(p.topic, format!("{TOPIC_PREFIX}/monitor/_plugin/players"));
+    assert_eq!(
+        String::from_utf8(p.payload.to_vec()).unwrap(),
+        "Test Player\nOther Player\nBühne"
+    );
+    check_ack(&mut connection, "_plugin", "list-players\nexecuted");
+
     mstarShutdown();
+
+    let p = wait_for_publish(&mut connection).expect("no message published");
+    assert_eq!(
+        p.topic,
+        format!("{TOPIC_PREFIX}/monitor/_plugin/availability")
+    );
+    assert_eq!(String::from_utf8(p.payload.to_vec()).unwrap(), "offline");
 }
@@ -0,0 +1,215 @@
+#![allow(non_snake_case)]
+
+mod callbacks;
+
+use callbacks::*;
+use core::time::Duration;
+use log::error;
+use rumqttc::v5::mqttbytes::v5::{Publish, PublishProperties};
+use rumqttc::v5::mqttbytes::QoS;
+use rumqttc::v5::{Client, Connection, Event, Incoming, MqttOptions};
+use std::ffi::{CStr, CString};
+use MStarPlayer_mqtt_plugin::*;
+
+/// Time to wait for MQTT messages.
+static TIMEOUT: Duration = Duration::new(1, 0);
+
+/// MQTT server to connect to for testing.
+static SERVER: &str = "127.0.0.1";
+static PORT: u16 = 1883;
+static TOPIC_PREFIX: &str = "integration-test-mqtt5";
+
+fn wait_for_publish_matching(
+    connection: &mut Connection,
+    predicate: impl Fn(&Publish) -> bool,
+) -> Option<Publish> {
+    loop {
+        match connection.recv_timeout(TIMEOUT) {
+            Ok(Ok(Event::Incoming(Incoming::Publish(p)))) => {
+                if predicate(&p) {
+                    return Some(p);
+                }
+            }
+            Ok(Ok(_)) => {}
+            Ok(Err(e)) => {
+                error!("{}", e);
+                return None;
+            }
+            Err(_) => {
+                error!("Timeout waiting for MQTT message");
+                return None;
+            }
+        }
+    }
+}
+
+/// Wait for the next live message on `topic`.
+fn wait_for_topic(connection: &mut Connection, topic: &str) -> Option<Publish> {
+    wait_for_publish_matching(connection, |p| !p.retain && p.topic == topic.as_bytes())
+}
+
+fn wait_for_suback(connection: &mut Connection) {
+    loop {
+        match connection.recv_timeout(TIMEOUT) {
+            Ok(Ok(Event::Incoming(Incoming::SubAck(_)))) => {
+                break;
+            }
+            Ok(Ok(_)) => {}
+            Ok(Err(e)) => {
+                error!("{}", e);
+                break;
+            }
+            Err(_) => {
+                error!("Timeout waiting for subscription");
+                break;
+            }
+        }
+    }
+}
+
+fn user_property<'a>(p: &'a Publish, name: &str) -> Option<&'a str> {
+    p.properties
+        .as_ref()?
+        .user_properties
+        .iter()
+        .find(|(key, _)| key == name)
+        .map(|(_, value)| value.as_str())
+}
+
+#[test]
+#[ignore]
+fn mqtt5_interaction() {
+    let init = plugin_interface_v2::Init {
+        listPlayers,
+        play,
+        stop,
+        next,
+        previous,
+        listTracks,
+        setTrackVolume,
+    };
+    mstarInit(&init);
+
+    // Custom topic templates and aliases are used as well.
+    let input_configuration = serde_json::json!({
+        "version": 1,
+        "server": SERVER,
+        "port": PORT,
+        "client_name": "MStarPlayer-MQTT5-sut",
+        "topic_prefix": TOPIC_PREFIX,
+        "protocol_version": "5",
+        "position_expiry": 10,
+        "monitor_topic_template": "{prefix}/{player}/state/{track}/{event}",
+        "control_topic_template": "{prefix}/{player}/set/{command}",
+        "aliases": { "Test Player": "main" },
+    });
+    let input_configuration_raw = CString::new(input_configuration.to_string()).unwrap();
+    unsafe {
+        mstarLoadConfiguration(input_configuration_raw.as_ptr());
+    }
+
+    let options = MqttOptions::new("MStarPlayer-MQTT5-test", SERVER, PORT);
+
+    let (client, mut connection) = Client::new(options, 10);
+
+    client
+        .subscribe(format!("{TOPIC_PREFIX}/#"), QoS::AtMostOnce)
+        .unwrap();
+    wait_for_suback(&mut connection);
+    wait_for_publish_matching(&mut connection, |p| {
+        p.topic == format!("{TOPIC_PREFIX}/_plugin/state/availability").as_bytes()
+            && p.payload.as_ref() == b"online"
+    })
+    .expect("plugin didn't announce that it's online");
+
+    let player_name = CString::new("Test Player").unwrap();
+    mstarPlayingStateChanged(player_name.as_ptr(), true);
+
+    let topic = format!("{TOPIC_PREFIX}/main/state/playing");
+    let p = wait_for_topic(&mut connection, &topic).expect("no message published");
+    assert_eq!(user_property(&p, "player"), Some("Test Player"));
+    assert_eq!(user_property(&p, "event"), Some("playing"));
+
+    let track_name = CString::new("Test Track").unwrap();
+    mstarTrackVolumeChanged(player_name.as_ptr(), track_name.as_ptr(), 0.5);
+
+    let topic = format!("{TOPIC_PREFIX}/main/state/Test Track/volume");
+    let p = wait_for_topic(&mut connection, &topic).expect("no message published");
+    assert_eq!(user_property(&p, "event"), Some("track/Test Track/volume"));
+
+    mstarPositionChanged(player_name.as_ptr(), 1.0);
+
+    let topic = format!("{TOPIC_PREFIX}/main/state/position");
+    let p = wait_for_topic(&mut connection, &topic).expect("no message published");
+    assert_eq!(user_property(&p, "event"), Some("position"));
+    // The broker deducts the time the message was waiting.
+    let expiry = p.properties.unwrap().message_expiry_interval.unwrap();
+    assert!(expiry > 0 && expiry <= 10, "{expiry}");
+
+    {
+        let list_players_ctx = MockCallbacks::list_players_context();
+        list_players_ctx
+            .expect()
+            .returning(|_, callback, user_data| {
+                callback(c"Test Player".as_ptr(), user_data);
+            });
+        let play_ctx = MockCallbacks::play_context();
+        play_ctx
+            .expect()
+            .once()
+            .withf(|p| unsafe { CStr::from_ptr(*p) } == c"Test Player")
+            .return_const(());
+
+        client
+            .publish(
+                format!("{TOPIC_PREFIX}/main/set/play"),
+                QoS::AtLeastOnce,
+                false,
+                Vec::new(),
+            )
+            .unwrap();
+
+        let topic = format!("{TOPIC_PREFIX}/main/state/ack");
+        let p = wait_for_topic(&mut connection, &topic).expect("no message published");
+        assert_eq!(p.payload.as_ref(), b"play\nexecuted");
+    }
+
+    {
+        let ctx = MockCallbacks::list_players_context();
+        ctx.expect().once().returning(|_, callback, user_data| {
+            callback(c"Test Player".as_ptr(), user_data);
+        });
+
+        let properties = PublishProperties {
+            response_topic: Some(format!("{TOPIC_PREFIX}/reply")),
+            correlation_data: Some("request-1".into()),
+            ..Default::default()
+        };
+        client
+            .publish_with_properties(
+                format!("{TOPIC_PREFIX}/_plugin/set/list-players"),
+                QoS::AtLeastOnce,
+                false,
+                Vec::new(),
+                properties,
+            )
+            .unwrap();
+
+        let topic = format!("{TOPIC_PREFIX}/reply");
+        let p = wait_for_topic(&mut connection, &topic).expect("no message published");
+        assert_eq!(p.payload.as_ref(), b"Test Player");
+        assert_eq!(
+            p.properties.unwrap().correlation_data.as_deref(),
+            Some(b"request-1".as_slice())
+        );
+        // The acknowledgement goes to the response topic as well.
+        let p = wait_for_topic(&mut connection, &topic).expect("no message published");
+        assert_eq!(p.payload.as_ref(), b"list-players\nexecuted");
+        assert_eq!(
+            p.properties.unwrap().correlation_data.as_deref(),
+            Some(b"request-1".as_slice())
+        );
+    }
+
+    mstarShutdown();
+}
@@ -31,5 +31,20 @@ fn startup_shutdown() {
     mstarTrackVolumeChanged(empty_raw_string.as_ptr(), empty_raw_string.as_ptr(), 0.0);
     mstarPositionChanged(empty_raw_string.as_ptr(), 0.0);
 
+    // Not even with null pointers.
+    let null = std::ptr::null();
+    mstarPlayingStateChanged(null, true);
+    mstarNextEntrySelected(null);
+    mstarPreviousEntrySelected(null);
+    mstarPlaylistEntrySelected(null, 0, null, 0.0);
+    mstarPlaylistEntryDurationChanged(null, 0, 0.0);
+    mstarPlaylistEntryNameChanged(null, 0, null);
+    mstarTrackVolumeChanged(null, null, 0.0);
+    mstarPositionChanged(null, 0.0);
+    unsafe {
+        mstarLoadConfiguration(null);
+    }
+    mstarFreeConfigurationText(null);
+
     mstarShutdown();
 }